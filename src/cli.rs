@@ -3,11 +3,16 @@ mod root;
 
 use std::{env, num::NonZeroUsize, path::PathBuf};
 
-use clap::{self, command, Arg, ArgMatches, ValueHint};
-use miette::{Context, IntoDiagnostic, Result};
+use clap::{self, command, Arg, ArgAction, ArgMatches, ValueHint};
+use miette::{Report, Result};
 
 pub(crate) use root::Root;
 
+use crate::{
+    options::{CleanMode, MinifyOptions, Mode},
+    reporter::{ReportFile, ReportFormat},
+};
+
 pub fn cli() -> ArgMatches {
     command!()
         .arg(
@@ -25,7 +30,10 @@ By default, oxbuild will look for the nearest package.json starting at your CWD
                 .long("config")
                 .value_hint(ValueHint::FilePath)
                 .value_parser(path_parser)
-                .help("Path to .oxbuildrc. Not yet supported"),
+                .help("Path to oxbuild.json or .oxbuildrc")
+                .long_help("Path to oxbuild.json or .oxbuildrc.
+
+By default, Oxbuild will look for oxbuild.json, .oxbuild.json or .oxbuildrc next to the nearest package.json file. Settings in this file take precedence over tsconfig.json, while CLI flags take precedence over both."),
         )
         .arg(
             Arg::new("tsconfig")
@@ -46,6 +54,97 @@ By default, Oxbuild will look for a tsconfig.json next to the nearest package.js
                 .help("Number of threads to use")
                 .long_help("Number of threads to use. Defaults to the number of logical cores available on the system."),
         )
+        .arg(
+            Arg::new("watch")
+                .short('w')
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .help("Watch source files and recompile them when they change")
+                .long_help("Watch source files and recompile them when they change.
+
+After the initial build, Oxbuild will keep running and only recompile files that were added or modified. Outputs for deleted source files are removed from the output directory."),
+        )
+        .arg(
+            Arg::new("clean")
+                .long("clean")
+                .value_name("MODE")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("all")
+                .value_parser(["all", "stale"])
+                .help("Remove old outputs from the output directory before building")
+                .long_help("Remove old outputs from the output directory before building.
+
+`--clean` or `--clean=all` deletes the output directory entirely. `--clean=stale` only deletes outputs whose source file no longer exists, such as files left behind by a rename. Oxbuild refuses to clean an output directory that contains your source files."),
+        )
+        .arg(
+            Arg::new("no_cache")
+                .long("no-cache")
+                .action(ArgAction::SetTrue)
+                .help("Compile every file, ignoring outputs cached by previous builds")
+                .long_help("Compile every file, ignoring outputs cached by previous builds.
+
+By default, Oxbuild saves compiled outputs in the nearest node_modules/.cache/oxbuild and reuses them for files that haven't changed. Projects without a node_modules folder above them aren't cached. The cache is ignored whenever Oxbuild's version or your compiler options change."),
+        )
+        .arg(
+            Arg::new("mode")
+                .short('m')
+                .long("mode")
+                .value_parser(["development", "production"])
+                .help("Build profile, which switches several settings together")
+                .long_help("Build profile, which switches several settings together.
+
+`development` turns on JSX development helpers and includes source text in source maps. `production` minifies code (see --minify), removes comments and turns JSX development helpers off. Both replace process.env.NODE_ENV with the mode's name. Explicit settings, like tsconfig's inlineSources or jsx.development in your oxbuild config, still take precedence.
+
+Defaults to the NODE_ENV environment variable when it is `development` or `production`."),
+        )
+        .arg(
+            Arg::new("define")
+                .long("define")
+                .value_name("KEY=VALUE")
+                .action(ArgAction::Append)
+                .value_parser(define_parser)
+                .help("Replace a global expression with a constant, e.g. __DEV__=false")
+                .long_help(r#"Replace a global expression with a constant, e.g. __DEV__=false. May be passed several times.
+
+Keys may be identifiers (__DEV__), member expressions (process.env.NODE_ENV) or import.meta properties (import.meta.env.MODE). Values are JavaScript expressions, so strings must be quoted: --define 'import.meta.env.MODE="production"'. Branches that can no longer run, like `if (false)`, are removed afterwards. These take precedence over `define` in your oxbuild config."#),
+        )
+        .arg(
+            Arg::new("minify")
+                .long("minify")
+                .value_name("OPTIONS")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("all")
+                .value_parser(|s: &str| s.parse::<MinifyOptions>())
+                .help("Minify compiled code")
+                .long_help("Minify compiled code.
+
+`--minify` or `--minify=all` removes whitespace and comments, compresses code into shorter equivalents and renames local variables. Pass a comma-separated list to pick some of these, e.g. `--minify=whitespace,compress`, or `--minify=none` to turn minification off in production mode. Top-level names are never renamed, since they may be exports. Source maps point at your original code either way."),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_parser(["graphical", "json", "github"])
+                .help("How diagnostics are printed")
+                .long_help("How diagnostics are printed.
+
+`graphical` prints human-readable reports with code frames. `json` prints one JSON object per diagnostic, one per line, for tools and editor integrations; other messages go to stderr. `github` prints GitHub Actions workflow commands, so diagnostics are shown as annotations on pull requests.
+
+Defaults to `github` when the GITHUB_ACTIONS environment variable is `true`, and `graphical` otherwise."),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_name("KIND=PATH")
+                .action(ArgAction::Append)
+                .value_parser(|s: &str| s.parse::<ReportFile>())
+                .help("Also write diagnostics to a report file, e.g. sarif=oxbuild.sarif")
+                .long_help("Also write diagnostics to a report file, e.g. sarif=oxbuild.sarif. May be passed several times.
+
+`sarif` writes a SARIF 2.1.0 log for code scanning dashboards. `junit` writes JUnit XML with one test case per compiled file, which fails if the file had errors. Reports are rewritten after every build."),
+        )
         .get_matches()
 }
 
@@ -53,12 +152,37 @@ fn path_parser(v: &str) -> Result<PathBuf> {
     Ok(PathBuf::from(v))
 }
 
+fn define_parser(v: &str) -> Result<(String, String), String> {
+    match v.split_once('=') {
+        Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => Err(format!(
+            "Invalid define '{v}'. Expected <key>=<value>, e.g. __DEV__=false"
+        )),
+    }
+}
+
 #[non_exhaustive]
 pub struct CliOptions {
     pub root: Root,
     pub config: Option<PathBuf>,
     pub tsconfig: Option<PathBuf>,
-    pub num_threads: NonZeroUsize,
+    /// [`None`] when not provided, so config files can set it.
+    pub num_threads: Option<NonZeroUsize>,
+    pub watch: bool,
+    /// [`None`] when not provided, so config files can set it.
+    pub clean: Option<CleanMode>,
+    /// `true` when `--no-cache` was passed.
+    pub no_cache: bool,
+    /// [`None`] when not provided, so config files and `NODE_ENV` can set it.
+    pub mode: Option<Mode>,
+    /// Replacements from `--define`, in the order they were passed.
+    pub defines: Vec<(String, String)>,
+    /// [`None`] when not provided, so config files and the mode can set it.
+    pub minify: Option<MinifyOptions>,
+    pub format: ReportFormat,
+    pub reports: Vec<ReportFile>,
 }
 
 impl CliOptions {
@@ -87,18 +211,61 @@ impl CliOptions {
         let tsconfig =
             root.resolve_file(matches.get_one::<PathBuf>("tsconfig"), ["tsconfig.json"])?;
 
-        let num_threads = match matches.get_one::<NonZeroUsize>("num_threads") {
-            Some(n) => *n,
-            None => {
-                std::thread::available_parallelism().into_diagnostic().with_context(|| "Failed to determine number of threads available. Please provide this explicitly using -t,--threads <n>")?
+        let num_threads = matches.get_one::<NonZeroUsize>("num_threads").copied();
+
+        let watch = matches.get_flag("watch");
+
+        let clean = matches
+            .get_one::<String>("clean")
+            .map(|mode| match mode.as_str() {
+                "stale" => CleanMode::Stale,
+                _ => CleanMode::All,
+            });
+
+        let no_cache = matches.get_flag("no_cache");
+
+        let mode = matches
+            .get_one::<String>("mode")
+            .map(|mode| mode.parse())
+            .transpose()
+            .map_err(Report::msg)?;
+
+        let defines = matches
+            .get_many::<(String, String)>("define")
+            .unwrap_or_default()
+            .cloned()
+            .collect();
+
+        let minify = matches.get_one::<MinifyOptions>("minify").copied();
+
+        let format = match matches.get_one::<String>("format") {
+            Some(format) => format.parse().map_err(Report::msg)?,
+            None if env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true") => {
+                debug!("Running in GitHub Actions, reporting diagnostics as annotations");
+                ReportFormat::Github
             }
+            None => ReportFormat::default(),
         };
 
+        let reports = matches
+            .get_many::<ReportFile>("report")
+            .unwrap_or_default()
+            .cloned()
+            .collect();
+
         Ok(Self {
             root,
             config,
             tsconfig,
             num_threads,
+            watch,
+            clean,
+            no_cache,
+            mode,
+            defines,
+            minify,
+            format,
+            reports,
         })
     }
 }
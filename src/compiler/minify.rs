@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+
+use oxc::{
+    allocator::Allocator,
+    ast::{
+        ast::{BindingIdentifier, IdentifierReference, Program},
+        Visit,
+    },
+    mangler::Mangler,
+    minifier::{CompressOptions, Compressor},
+    semantic::{SemanticBuilder, SymbolId, SymbolTable},
+};
+
+use crate::options::MinifyOptions;
+
+/// Compress and mangle `program`. Whitespace is removed later, by codegen.
+///
+/// Returns the mangler codegen should print names with, if mangling is on.
+pub(super) fn minify<'a>(
+    options: MinifyOptions,
+    allocator: &'a Allocator,
+    program: &mut Program<'a>,
+) -> Option<Mangler> {
+    if options.compress {
+        Compressor::new(allocator, CompressOptions::default()).build(program);
+    }
+    if !options.mangle {
+        return None;
+    }
+    let mangler = Mangler::new().build(program);
+
+    // top-level names are exports, or globals in scripts, so they keep their
+    // names. Codegen prints the original name of identifiers without ids.
+    let (symbols, scopes) = SemanticBuilder::new("")
+        .build(program)
+        .semantic
+        .into_symbol_table_and_scope_tree();
+    let top_level = scopes
+        .get_bindings(scopes.root_scope_id())
+        .values()
+        .copied()
+        .collect();
+    TopLevelNames {
+        symbols: &symbols,
+        top_level,
+    }
+    .visit_program(program);
+
+    Some(mangler)
+}
+
+/// Removes ids from identifiers that refer to top-level bindings, so the
+/// mangler's names aren't used for them.
+struct TopLevelNames<'s> {
+    symbols: &'s SymbolTable,
+    top_level: HashSet<SymbolId>,
+}
+
+impl<'a> Visit<'a> for TopLevelNames<'_> {
+    fn visit_binding_identifier(&mut self, it: &BindingIdentifier<'a>) {
+        if it
+            .symbol_id
+            .get()
+            .is_some_and(|id| self.top_level.contains(&id))
+        {
+            it.symbol_id.set(None);
+        }
+    }
+
+    fn visit_identifier_reference(&mut self, it: &IdentifierReference<'a>) {
+        let symbol_id = it
+            .reference_id
+            .get()
+            .and_then(|id| self.symbols.get_reference(id).symbol_id());
+        if symbol_id.is_some_and(|id| self.top_level.contains(&id)) {
+            it.reference_id.set(None);
+        }
+    }
+}
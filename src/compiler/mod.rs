@@ -1,28 +1,35 @@
 #![allow(dead_code, unused_imports, unused_variables)]
+mod minify;
+mod module;
 mod options;
+mod paths;
+mod target;
 
 use oxc::{
     ast::{ast::Program, Trivias},
-    codegen::CodegenReturn,
+    codegen::{CodegenOptions, CodegenReturn},
     isolated_declarations::IsolatedDeclarationsOptions,
-    transformer::{ES2015Options, JsxOptions},
+    transformer::{ES2015Options, JsxOptions, ReplaceGlobalDefines, ReplaceGlobalDefinesConfig},
 };
-use std::{fs, path::Path};
+use std::{collections::HashSet, fs, path::Path};
 
 use oxc::{
-    allocator::Allocator,
+    allocator::{Allocator, CloneIn},
     codegen::{Codegen, CommentOptions},
-    diagnostics::OxcDiagnostic,
+    diagnostics::{OxcDiagnostic, Severity},
     isolated_declarations::{IsolatedDeclarations, IsolatedDeclarationsReturn},
-    minifier::Minifier,
+    minifier::{CompressOptions, Compressor},
     parser::{Parser, ParserReturn},
     semantic::{Semantic, SemanticBuilder, SemanticBuilderReturn},
-    sourcemap::SourceMap,
+    sourcemap::{ConcatSourceMapBuilder, SourceMap},
     span::SourceType,
     transformer::{TransformOptions, Transformer, TransformerReturn},
 };
 
-pub use options::CompileOptions;
+pub use module::ModuleFormat;
+pub use options::{CompileOptions, OutputOptions};
+pub use paths::{resolutions_match, rewrite_declaration_file, PathAliases, Resolutions};
+pub use target::EsTarget;
 
 use crate::options::DeclarationsOptions;
 
@@ -30,8 +37,23 @@ static_assertions::assert_impl_all!(CompileOptions: Send, Sync);
 
 #[derive(Debug, Clone)]
 pub struct CompiledOutput {
+    /// Compiled code for each of [`CompileOptions::outputs`], in the same
+    /// order.
+    pub code: Vec<CompiledCode>,
+    /// What specifiers resolved to when they were rewritten.
+    pub resolutions: Resolutions,
+    /// Problems that didn't stop code from being emitted. These may still be
+    /// errors that should fail the build, e.g. when declarations couldn't be
+    /// generated.
+    pub diagnostics: Vec<OxcDiagnostic>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledCode {
     pub source_text: String,
     pub source_map: Option<SourceMap>,
+    /// Declarations are emitted for each output too, since their specifiers
+    /// point at that output's files.
     pub declarations: Option<String>,
     pub declarations_map: Option<SourceMap>,
 }
@@ -78,37 +100,125 @@ pub fn compile(
     // produce .d.ts files
     let id = options
         .declarations_options()
-        .map(|opts| {
-            isolated_declarations(
-                opts,
-                &allocator,
-                &program,
-                source_text,
-                source_name,
-                trivias.clone(),
-            )
-        })
+        .map(|opts| isolated_declarations(opts, &allocator, &program, source_text, &trivias))
         .transpose();
 
-    let (id, id_map) = match id {
-        Ok(Some(CodegenReturn { code, map })) => (Some(code), Some(map)),
-        Ok(None) => (None, None),
+    let mut diagnostics = vec![];
+    let id = match id {
+        Ok(id) => id,
         Err(id_errors) => {
-            errors.extend(id_errors);
-            (None, None)
+            let fail = options
+                .declarations_options()
+                .map_or(true, |opts| opts.fail_on_error);
+            diagnostics.extend(id_errors.into_iter().map(|error| {
+                if fail {
+                    error
+                } else {
+                    error.with_severity(Severity::Warning)
+                }
+            }));
+            None
         }
     };
+    let mut resolutions = Resolutions::new();
+
+    // declarations share nodes with `program`, so they are printed before
+    // anything is transformed
+    let mut declarations = vec![];
+    if let (Some(id), Some(id_options)) = (&id, options.declarations_options()) {
+        for output in options.outputs() {
+            let mut id = id.clone_in(&allocator);
+            paths::rewrite_specifiers(
+                options,
+                output.module_format,
+                &allocator,
+                &mut id,
+                source_path,
+                &mut resolutions,
+            );
+            let CodegenReturn { code, map } =
+                print_declarations(id_options, &id, source_text, source_name, trivias.clone());
+            declarations.push((code, map));
+        }
+    }
+
+    // transforming modifies the AST, so every other output needs its own copy.
+    // Copies are made before anything is transformed.
+    let (first, rest) = options.outputs().split_first().unwrap();
+    let mut copies: Vec<_> = rest.iter().map(|_| program.clone_in(&allocator)).collect();
+
+    let mut code = Vec::with_capacity(options.outputs().len());
+    paths::rewrite_specifiers(
+        options,
+        first.module_format,
+        &allocator,
+        &mut program,
+        source_path,
+        &mut resolutions,
+    );
+    code.push(transform(
+        options,
+        first,
+        &allocator,
+        semantic,
+        &mut program,
+        source_path,
+        &mut diagnostics,
+    ));
+    for (output, program) in rest.iter().zip(&mut copies) {
+        paths::rewrite_specifiers(
+            options,
+            output.module_format,
+            &allocator,
+            program,
+            source_path,
+            &mut resolutions,
+        );
+        // copies don't have symbols: `clone_in` resets symbol, reference and
+        // scope ids, and symbol tables can't be cloned. So each copy gets its
+        // own semantic pass, without the error checks the original had.
+        let semantic = SemanticBuilder::new(source_text)
+            .with_trivias(trivias.clone())
+            .build(program)
+            .semantic;
+        code.push(transform(
+            options,
+            output,
+            &allocator,
+            semantic,
+            program,
+            source_path,
+            &mut diagnostics,
+        ));
+    }
 
-    let CodegenReturn {
-        code: output_text,
-        map: source_map,
-    } = transform(&allocator, semantic, &mut program, source_path);
+    // each output is transformed separately, but they usually have the same
+    // problems. Only report each one once.
+    let mut seen = HashSet::new();
+    diagnostics.retain(|diagnostic| {
+        let spans = diagnostic.labels.iter().flatten();
+        seen.insert((
+            diagnostic.message.clone(),
+            spans
+                .map(|label| (label.offset(), label.len()))
+                .collect::<Vec<_>>(),
+        ))
+    });
+
+    // a variant failed its target checks. Everything found so far is
+    // reported, not just those errors
+    let Some(mut code) = code.into_iter().collect::<Option<Vec<_>>>() else {
+        return Err(diagnostics);
+    };
+    for (code, (declarations, declarations_map)) in code.iter_mut().zip(declarations) {
+        code.declarations = Some(declarations);
+        code.declarations_map = declarations_map;
+    }
 
     Ok(CompiledOutput {
-        source_text: output_text,
-        source_map,
-        declarations: id,
-        declarations_map: id_map.flatten(),
+        code,
+        resolutions,
+        diagnostics,
     })
 }
 
@@ -117,15 +227,14 @@ fn isolated_declarations<'a>(
     allocator: &'a Allocator,
     program: &Program<'a>,
     source_text: &'a str,
-    source_name: &'a str,
-    trivias: Trivias,
-) -> Result<CodegenReturn, Vec<OxcDiagnostic>> {
+    trivias: &Trivias,
+) -> Result<Program<'a>, Vec<OxcDiagnostic>> {
     let IsolatedDeclarationsReturn {
         program, errors, ..
     } = IsolatedDeclarations::new(
         allocator,
         source_text,
-        &trivias,
+        trivias,
         IsolatedDeclarationsOptions {
             strip_internal: options.strip_internal,
         },
@@ -135,48 +244,65 @@ fn isolated_declarations<'a>(
     if !errors.is_empty() {
         return Err(errors);
     }
+    Ok(program)
+}
 
-    let result = Codegen::new()
+fn print_declarations(
+    options: &DeclarationsOptions,
+    program: &Program<'_>,
+    source_text: &str,
+    source_name: &str,
+    trivias: Trivias,
+) -> CodegenReturn {
+    let mut codegen = Codegen::new()
         .with_source_text(source_text)
         .with_capacity(source_text.len())
         .enable_comment(
             source_text,
             trivias,
             CommentOptions {
-                preserve_annotate_comments: false,
+                preserve_annotate_comments: true,
             },
-        )
-        // .enable_source_map(source_name, source_text)
-        .build(&program);
+        );
+    if options.declaration_map {
+        codegen = codegen.enable_source_map(source_name, source_text);
+    }
 
-    Ok(result)
+    codegen.build(program)
 }
 
+/// Compile `program` for one output. Problems are added to `diagnostics`,
+/// and [`None`] is returned when the program has syntax the output's target
+/// or module format can't express.
 fn transform<'a>(
+    options: &CompileOptions,
+    output: &OutputOptions,
     allocator: &'a Allocator,
     semantic: Semantic<'a>,
     program: &mut Program<'a>,
     source_path: &Path,
-) -> CodegenReturn {
+    diagnostics: &mut Vec<OxcDiagnostic>,
+) -> Option<CompiledCode> {
     let trivias = semantic.trivias().clone();
     let source_text = semantic.source_text();
 
-    let options = TransformOptions {
-        react: JsxOptions {
-            jsx_plugin: true,
-            display_name_plugin: true,
-            jsx_source_plugin: true,
-            development: true,
-            ..Default::default()
-        },
+    let arrow_errors = output.target.check_arrow_functions(program);
+    if !arrow_errors.is_empty() {
+        diagnostics.extend(arrow_errors);
+        return None;
+    }
+
+    let mut transform_options = TransformOptions {
+        react: options.jsx().clone(),
         ..Default::default()
     };
+    output.target.apply(&mut transform_options);
     let transformer = Transformer::new(
         allocator,
         source_path,
         source_text,
         trivias.clone(),
-        options,
+        transform_options,
     );
     let (symbols, scopes) = semantic.into_symbol_table_and_scope_tree();
 
@@ -185,11 +311,137 @@ fn transform<'a>(
         symbols,
         scopes,
     } = transformer.build_with_symbols_and_scopes(symbols, scopes, program);
+    diagnostics.extend(errors);
 
-    let codegen = Codegen::new()
-        .enable_comment(source_text, trivias.clone(), Default::default())
-        .enable_source_map(source_path.as_os_str().to_str().unwrap(), source_text);
-    //.with_mangler(Some(Default::default()));
+    if let Some(config) = options.defines() {
+        // TypeScript `declare`s were removed by the transformer, but are still
+        // in the symbol table and would hide globals like `__DEV__`
+        let (symbols, scopes) = SemanticBuilder::new(source_text)
+            .build(program)
+            .semantic
+            .into_symbol_table_and_scope_tree();
+        let defined =
+            ReplaceGlobalDefines::new(allocator, config.clone()).build(symbols, scopes, program);
+        // remove branches that can no longer run, e.g.
+        // `if ("production" !== "production")`
+        Compressor::new(allocator, CompressOptions::dead_code_elimination())
+            .build_with_symbols_and_scopes(defined.symbols, defined.scopes, program);
+    }
 
-    codegen.build(program)
+    let syntax_errors = output.target.check_syntax(program);
+    if !syntax_errors.is_empty() {
+        diagnostics.extend(syntax_errors);
+        return None;
+    }
+
+    let preamble = match output.module_format.for_source(source_path) {
+        ModuleFormat::CommonJs => {
+            let commonjs_errors = module::check_commonjs(program);
+            if !commonjs_errors.is_empty() {
+                diagnostics.extend(commonjs_errors);
+                return None;
+            }
+            module::to_commonjs(allocator, program, output.target)
+        }
+        ModuleFormat::Esm | ModuleFormat::Preserve => None,
+    };
+
+    let mangler = minify::minify(options.minify(), allocator, program);
+
+    let trivias = if options.strip_comments() {
+        strip_comments(&trivias, source_text)
+    } else {
+        trivias
+    };
+    let mut codegen = Codegen::new()
+        .with_options(CodegenOptions {
+            minify: options.minify().whitespace,
+            ..Default::default()
+        })
+        .with_mangler(mangler)
+        .enable_comment(
+            source_text,
+            trivias,
+            CommentOptions {
+                preserve_annotate_comments: true,
+            },
+        );
+    if options.source_maps() {
+        codegen = codegen.enable_source_map(source_path.as_os_str().to_str().unwrap(), source_text);
+    }
+
+    let CodegenReturn { mut code, mut map } = codegen.build(program);
+    if let Some(preamble) = preamble {
+        // shift mappings down past the lines we're adding
+        let lines = u32::try_from(preamble.lines().count()).unwrap();
+        map = map
+            .map(|map| ConcatSourceMapBuilder::from_sourcemaps(&[(&map, lines)]).into_sourcemap());
+        code.insert_str(0, &preamble);
+    }
+
+    Some(CompiledCode {
+        source_text: code,
+        source_map: map,
+        declarations: None,
+        declarations_map: None,
+    })
+}
+
+/// Drop JSDoc comments, which are the only other comments codegen prints.
+fn strip_comments(trivias: &Trivias, source_text: &str) -> Trivias {
+    let comments = trivias
+        .comments()
+        .filter(|comment| !comment.is_jsdoc(source_text))
+        .copied()
+        .collect();
+    Trivias::new(comments, trivias.irregular_whitespaces().to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    /// Failing a target check doesn't hide problems found before it.
+    #[test]
+    fn test_target_errors_keep_other_diagnostics() {
+        let root = tempfile::tempdir().unwrap();
+        let options = CompileOptions::new(root.path().to_path_buf())
+            .with_d_ts(Some(DeclarationsOptions {
+                strip_internal: false,
+                declaration_map: false,
+                fail_on_error: true,
+            }))
+            .with_outputs(vec![OutputOptions {
+                target: EsTarget::ES2017,
+                module_format: ModuleFormat::Esm,
+            }]);
+        let source_text = "export function f(a: object) { return { ...a }; }\n";
+        let errors = compile(&options, Path::new("src/index.ts"), source_text).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_ref()).collect();
+        assert!(
+            messages.iter().any(|m| m.contains("isolatedDeclarations")),
+            "{messages:?}"
+        );
+        assert!(
+            messages.iter().any(|m| m.contains("Object spread")),
+            "{messages:?}"
+        );
+    }
+
+    #[test]
+    fn test_preserve_annotations() {
+        let source_text = "/** Docs */\nexport const a = /* @__PURE__ */ make();\n";
+        for strip in [false, true] {
+            let root = tempfile::tempdir().unwrap();
+            let options = CompileOptions::new(root.path().to_path_buf())
+                .with_source_maps(false)
+                .with_strip_comments(strip);
+            let output = compile(&options, Path::new("src/index.ts"), source_text).unwrap();
+            let code = &output.code[0].source_text;
+            assert!(code.contains("/* @__PURE__ */ make()"), "{code}");
+            assert_eq!(code.contains("/** Docs */"), !strip, "{code}");
+        }
+    }
 }
@@ -0,0 +1,1149 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fmt,
+    fmt::Write as _,
+    mem,
+    path::Path,
+    str::FromStr,
+};
+
+use oxc::{
+    allocator::Allocator,
+    ast::{
+        ast::{
+            Argument, ArrowFunctionExpression, AwaitExpression, BindingIdentifier,
+            BindingPatternKind, CallExpression, Declaration, ExportDefaultDeclarationKind,
+            Expression, ForOfStatement, Function, IdentifierReference, ImportDeclarationSpecifier,
+            JSXElementName, JSXMemberExpressionObject, MetaProperty, ObjectProperty, Program,
+            Statement, StringLiteral, TaggedTemplateExpression, VariableDeclarationKind,
+        },
+        visit::{walk, walk_mut},
+        AstBuilder, Visit, VisitMut, NONE,
+    },
+    diagnostics::OxcDiagnostic,
+    semantic::{ScopeFlags, SemanticBuilder, SymbolId, SymbolTable},
+    span::{Atom, Span, SPAN},
+    syntax::{identifier::is_identifier_name, number::NumberBase},
+};
+
+use super::EsTarget;
+
+/// Module system compiled code uses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleFormat {
+    /// Keep `import` and `export` statements as they are written.
+    #[default]
+    Preserve,
+    /// ECMAScript modules. Sources are already ESM, so this only affects
+    /// output file extensions.
+    Esm,
+    /// Convert `import` and `export` into `require` and `module.exports`.
+    CommonJs,
+}
+
+impl FromStr for ModuleFormat {
+    type Err = String;
+
+    /// Parse a `module` option from the oxbuild config or `compilerOptions`.
+    /// Matching is case-insensitive. `node16` and `nodenext` depend on the
+    /// package type, so they are not handled here.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = match s.to_ascii_lowercase().as_str() {
+            "preserve" => Self::Preserve,
+            "esm" | "es6" | "es2015" | "es2020" | "es2022" | "esnext" => Self::Esm,
+            "cjs" | "commonjs" => Self::CommonJs,
+            _ => return Err(format!("Unsupported module format '{s}'")),
+        };
+        Ok(format)
+    }
+}
+
+impl fmt::Display for ModuleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Preserve => "preserve",
+            Self::Esm => "esm",
+            Self::CommonJs => "commonjs",
+        };
+        f.write_str(name)
+    }
+}
+
+impl ModuleFormat {
+    /// The format a source file is compiled to. `.mts` and `.mjs` files are
+    /// always ESM, and `.cts` and `.cjs` files are always CommonJS.
+    pub fn for_source(self, source_path: &Path) -> Self {
+        match source_path.extension().and_then(OsStr::to_str) {
+            Some("mts" | "mjs") => Self::Esm,
+            Some("cts" | "cjs") => Self::CommonJs,
+            _ => self,
+        }
+    }
+
+    /// Extension for code compiled from `source_path`. Node decides how to
+    /// load `.js` files from the `type` field in `package.json`, so explicit
+    /// `.cjs` and `.mjs` extensions are used when the output doesn't match it.
+    /// Sources with an explicit module extension keep it.
+    pub fn js_extension(self, source_path: &Path, is_module_package: bool) -> &'static str {
+        match source_path.extension().and_then(OsStr::to_str) {
+            Some("mts" | "mjs") => return "mjs",
+            Some("cts" | "cjs") => return "cjs",
+            _ => {}
+        }
+        match (self, is_module_package) {
+            (Self::CommonJs, true) => "cjs",
+            (Self::Esm, false) => "mjs",
+            _ => "js",
+        }
+    }
+
+    /// Extension for declarations of code emitted with [`js_extension`].
+    ///
+    /// [`js_extension`]: ModuleFormat::js_extension
+    pub fn dts_extension(self, source_path: &Path, is_module_package: bool) -> &'static str {
+        match self.js_extension(source_path, is_module_package) {
+            "cjs" => "d.cts",
+            "mjs" => "d.mts",
+            _ => "d.ts",
+        }
+    }
+}
+
+/// Rewrite `import` and `export` statements in `program` into CommonJS.
+///
+/// Imports become `require` calls, and exports become getters on `exports`
+/// so they stay live. Returns code that must be prepended to the generated
+/// output, containing interop helpers and export definitions. Returns
+/// [`None`] if `program` has no module syntax.
+///
+/// References to imported bindings are rewritten into property accesses on
+/// the required module, like `_foo.bar`, so they stay live too.
+pub(super) fn to_commonjs<'a>(
+    allocator: &'a Allocator,
+    program: &mut Program<'a>,
+    target: EsTarget,
+) -> Option<String> {
+    if !program.body.iter().any(Statement::is_module_declaration) {
+        return None;
+    }
+
+    let mut names = NameCollector::default();
+    names.visit_program(program);
+    let (symbols, _) = SemanticBuilder::new("")
+        .build(program)
+        .semantic
+        .into_symbol_table_and_scope_tree();
+    let mut converter = ToCommonJs {
+        ast: AstBuilder::new(allocator),
+        kind: if target < EsTarget::ES2015 {
+            VariableDeclarationKind::Var
+        } else {
+            VariableDeclarationKind::Const
+        },
+        names: names.names,
+        exports: vec![],
+        helpers: Helpers::default(),
+        symbols,
+        imports: HashMap::new(),
+        import_names: HashMap::new(),
+    };
+
+    let body = mem::replace(&mut program.body, converter.ast.vec());
+    for stmt in body {
+        converter.convert(stmt, &mut program.body);
+    }
+    converter.visit_program(program);
+    program
+        .directives
+        .retain(|directive| directive.directive != "use strict");
+
+    Some(converter.preamble(program.hashbang.take().map(|h| h.value)))
+}
+
+/// Find ESM-only syntax that can't be converted to CommonJS and would throw
+/// when the output is loaded: `import.meta` and top-level `await`. Each kind
+/// of syntax is only reported once.
+pub(super) fn check_commonjs(program: &Program<'_>) -> Vec<OxcDiagnostic> {
+    let mut checker = CommonJsChecker::default();
+    checker.visit_program(program);
+    checker.errors
+}
+
+#[derive(Default)]
+struct CommonJsChecker {
+    /// How many functions deep the visitor is. `await` is only top-level at 0.
+    function_depth: usize,
+    import_meta: bool,
+    top_level_await: bool,
+    errors: Vec<OxcDiagnostic>,
+}
+
+impl CommonJsChecker {
+    fn report_await(&mut self, span: Span) {
+        if self.function_depth > 0 || mem::replace(&mut self.top_level_await, true) {
+            return;
+        }
+        self.errors.push(
+            OxcDiagnostic::error("Top-level `await` cannot be compiled to CommonJS")
+                .with_label(span)
+                .with_help("Move it into an async function, or set `module` to `esm`"),
+        );
+    }
+}
+
+impl<'a> Visit<'a> for CommonJsChecker {
+    fn visit_meta_property(&mut self, it: &MetaProperty<'a>) {
+        if it.meta.name == "import" && !mem::replace(&mut self.import_meta, true) {
+            self.errors.push(
+                OxcDiagnostic::error("`import.meta` cannot be compiled to CommonJS")
+                    .with_label(it.span)
+                    .with_help(
+                        "Use `__filename` and `__dirname` instead, or set `module` to `esm`",
+                    ),
+            );
+        }
+    }
+
+    fn visit_await_expression(&mut self, it: &AwaitExpression<'a>) {
+        self.report_await(it.span);
+        walk::walk_await_expression(self, it);
+    }
+
+    fn visit_for_of_statement(&mut self, it: &ForOfStatement<'a>) {
+        if it.r#await {
+            self.report_await(it.span);
+        }
+        walk::walk_for_of_statement(self, it);
+    }
+
+    fn visit_function(&mut self, it: &Function<'a>, flags: ScopeFlags) {
+        self.function_depth += 1;
+        walk::walk_function(self, it, flags);
+        self.function_depth -= 1;
+    }
+
+    fn visit_arrow_function_expression(&mut self, it: &ArrowFunctionExpression<'a>) {
+        self.function_depth += 1;
+        walk::walk_arrow_function_expression(self, it);
+        self.function_depth -= 1;
+    }
+}
+
+#[derive(Default)]
+struct Helpers {
+    import_default: bool,
+    import_star: bool,
+    export_star: bool,
+}
+
+struct ToCommonJs<'a> {
+    ast: AstBuilder<'a>,
+    kind: VariableDeclarationKind,
+    /// Every identifier in the program. Temporaries must not collide with
+    /// these.
+    names: HashSet<String>,
+    /// Exported names, and the expression their getter returns.
+    exports: Vec<(String, String)>,
+    helpers: Helpers,
+    symbols: SymbolTable,
+    /// Imported bindings, and the module variable and property they are
+    /// read from.
+    imports: HashMap<SymbolId, (String, String)>,
+    /// Imported bindings by name, and the expression that reads them. Used
+    /// for `export { foo }` of an imported `foo`.
+    import_names: HashMap<String, String>,
+}
+
+impl<'a> ToCommonJs<'a> {
+    fn convert(&mut self, stmt: Statement<'a>, out: &mut oxc::allocator::Vec<'a, Statement<'a>>) {
+        match stmt {
+            Statement::ImportDeclaration(decl) => {
+                let decl = decl.unbox();
+                let span = decl.span;
+                let specifiers = decl.specifiers.unwrap_or_else(|| self.ast.vec());
+                if specifiers.is_empty() {
+                    // import "foo"
+                    out.push(
+                        self.ast
+                            .statement_expression(span, self.require(decl.source)),
+                    );
+                    return;
+                }
+
+                let has_default = specifiers.iter().any(|specifier| {
+                    matches!(
+                        specifier,
+                        ImportDeclarationSpecifier::ImportDefaultSpecifier(_)
+                    )
+                });
+                let module = match specifiers.iter().find_map(|specifier| match specifier {
+                    ImportDeclarationSpecifier::ImportNamespaceSpecifier(s) => Some(&s.local),
+                    _ => None,
+                }) {
+                    // import * as ns from "foo"
+                    Some(local) => {
+                        self.helpers.import_star = true;
+                        let init = self.call("__importStar", self.require(decl.source.clone()));
+                        out.push(self.declare(span, &local.name, init));
+                        local.name.to_string()
+                    }
+                    None => {
+                        let require = self.require(decl.source.clone());
+                        // like `tsc`, the default export of a CommonJS module
+                        // is the module itself
+                        let init = match (has_default, specifiers.len() > 1) {
+                            // import foo, { bar } from "foo"
+                            (true, true) => {
+                                self.helpers.import_star = true;
+                                self.call("__importStar", require)
+                            }
+                            // import foo from "foo"
+                            (true, false) => {
+                                self.helpers.import_default = true;
+                                self.call("__importDefault", require)
+                            }
+                            // import { foo, bar as baz } from "foo"
+                            (false, _) => require,
+                        };
+                        let name = self.temporary(&module_variable_name(&decl.source.value));
+                        out.push(self.declare(span, &name, init));
+                        name
+                    }
+                };
+                for specifier in &specifiers {
+                    let (local, imported) = match specifier {
+                        ImportDeclarationSpecifier::ImportDefaultSpecifier(s) => {
+                            (&s.local, "default".to_string())
+                        }
+                        ImportDeclarationSpecifier::ImportSpecifier(s) => {
+                            (&s.local, s.imported.name().to_string())
+                        }
+                        ImportDeclarationSpecifier::ImportNamespaceSpecifier(_) => continue,
+                    };
+                    self.import_names
+                        .insert(local.name.to_string(), member_text(&module, &imported));
+                    if let Some(symbol_id) = local.symbol_id.get() {
+                        self.imports.insert(symbol_id, (module.clone(), imported));
+                    }
+                }
+            }
+
+            Statement::ExportNamedDeclaration(decl) => {
+                let mut decl = decl.unbox();
+                if let Some(declaration) = decl.declaration.take() {
+                    // export const foo = 1
+                    if declaration.is_typescript_syntax() {
+                        return;
+                    }
+                    for name in declared_names(&declaration) {
+                        self.exports.push((name.clone(), name));
+                    }
+                    out.push(Statement::from(declaration));
+                    return;
+                }
+
+                let specifiers = decl.specifiers.iter().filter(|s| s.export_kind.is_value());
+                match decl.source {
+                    // export { foo, bar as baz }
+                    None => {
+                        for s in specifiers {
+                            let local = s.local.name().to_string();
+                            self.exports.push((s.exported.name().to_string(), local));
+                        }
+                    }
+                    // export { foo, bar as baz } from "foo"
+                    Some(source) => {
+                        let mut module = None;
+                        for s in specifiers {
+                            let module = self.module_binding(&source, decl.span, &mut module, out);
+                            let imported = s.local.name();
+                            let getter = if imported == "default" {
+                                self.helpers.import_default = true;
+                                format!("__importDefault({module}).default")
+                            } else {
+                                member_text(&module, &imported)
+                            };
+                            self.exports.push((s.exported.name().to_string(), getter));
+                        }
+                    }
+                }
+            }
+
+            Statement::ExportDefaultDeclaration(decl) => {
+                let decl = decl.unbox();
+                let span = decl.span;
+                match decl.declaration {
+                    // export default function foo() {}
+                    ExportDefaultDeclarationKind::FunctionDeclaration(mut func) => {
+                        let name = self.binding_for(&mut func.id);
+                        self.exports.push(("default".to_string(), name));
+                        out.push(Statement::FunctionDeclaration(func));
+                    }
+                    // export default class Foo {}
+                    ExportDefaultDeclarationKind::ClassDeclaration(mut class) => {
+                        let name = self.binding_for(&mut class.id);
+                        self.exports.push(("default".to_string(), name));
+                        out.push(Statement::ClassDeclaration(class));
+                    }
+                    ExportDefaultDeclarationKind::TSInterfaceDeclaration(_) => {}
+                    // export default foo + bar
+                    kind => {
+                        let name = self.temporary("_default");
+                        let init = kind.into_expression();
+                        out.push(self.declare(span, &name, init));
+                        self.exports.push(("default".to_string(), name));
+                    }
+                }
+            }
+
+            Statement::ExportAllDeclaration(decl) => {
+                let decl = decl.unbox();
+                if decl.export_kind.is_type() {
+                    return;
+                }
+                let span = decl.span;
+                match decl.exported {
+                    // export * as ns from "foo"
+                    Some(exported) => {
+                        self.helpers.import_star = true;
+                        let name = self.temporary(&format!("_{}", exported.name()));
+                        let init = self.call("__importStar", self.require(decl.source));
+                        out.push(self.declare(span, &name, init));
+                        self.exports.push((exported.name().to_string(), name));
+                    }
+                    // export * from "foo"
+                    None => {
+                        self.helpers.export_star = true;
+                        let mut arguments = self.ast.vec();
+                        arguments.push(Argument::from(self.require(decl.source)));
+                        arguments.push(Argument::from(self.ident("exports")));
+                        let call = self.ast.expression_call(
+                            SPAN,
+                            self.ident("__exportStar"),
+                            NONE,
+                            arguments,
+                            false,
+                        );
+                        out.push(self.ast.statement_expression(span, call));
+                    }
+                }
+            }
+
+            stmt => out.push(stmt),
+        }
+    }
+
+    /// Name of the variable holding `require(source)` for the current
+    /// statement, declaring it the first time it is needed.
+    fn module_binding(
+        &mut self,
+        source: &StringLiteral<'a>,
+        span: Span,
+        module: &mut Option<String>,
+        out: &mut oxc::allocator::Vec<'a, Statement<'a>>,
+    ) -> String {
+        if let Some(module) = module {
+            return module.clone();
+        }
+        let name = self.temporary(&module_variable_name(&source.value));
+        let init = self.require(source.clone());
+        out.push(self.declare(span, &name, init));
+        *module = Some(name.clone());
+        name
+    }
+
+    /// Name of an exported function or class, giving it one if it is
+    /// anonymous.
+    fn binding_for(&mut self, id: &mut Option<BindingIdentifier<'a>>) -> String {
+        match id {
+            Some(id) => id.name.to_string(),
+            None => {
+                let name = self.temporary("_default");
+                *id = Some(self.ast.binding_identifier(SPAN, self.ast.atom(&name)));
+                name
+            }
+        }
+    }
+
+    /// Pick a variable name based on `base` that isn't used anywhere else.
+    fn temporary(&mut self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut i = 2;
+        while self.names.contains(&name) {
+            name = format!("{base}{i}");
+            i += 1;
+        }
+        self.names.insert(name.clone());
+        name
+    }
+
+    fn declare(&self, span: Span, name: &str, init: Expression<'a>) -> Statement<'a> {
+        let id = self.ast.binding_pattern(
+            self.ast
+                .binding_pattern_kind_binding_identifier(SPAN, self.ast.atom(name)),
+            NONE,
+            false,
+        );
+        let declarator = self
+            .ast
+            .variable_declarator(SPAN, self.kind, id, Some(init), false);
+        let declaration =
+            self.ast
+                .declaration_variable(span, self.kind, self.ast.vec1(declarator), false);
+        Statement::from(declaration)
+    }
+
+    fn require(&self, source: StringLiteral<'a>) -> Expression<'a> {
+        let source = self.ast.expression_from_string_literal(source);
+        self.call("require", source)
+    }
+
+    fn call(&self, callee: &str, argument: Expression<'a>) -> Expression<'a> {
+        self.ast.expression_call(
+            SPAN,
+            self.ident(callee),
+            NONE,
+            self.ast.vec1(Argument::from(argument)),
+            false,
+        )
+    }
+
+    fn ident(&self, name: &str) -> Expression<'a> {
+        self.ast
+            .expression_identifier_reference(SPAN, self.ast.atom(name))
+    }
+
+    fn member(&self, object: Expression<'a>, property: &str) -> Expression<'a> {
+        let member = if is_identifier_name(property) {
+            self.ast.member_expression_static(
+                SPAN,
+                object,
+                self.ast.identifier_name(SPAN, self.ast.atom(property)),
+                false,
+            )
+        } else {
+            let property = self
+                .ast
+                .expression_string_literal(SPAN, self.ast.atom(property));
+            self.ast
+                .member_expression_computed(SPAN, object, property, false)
+        };
+        self.ast.expression_member(member)
+    }
+
+    /// Code that runs before the converted module body.
+    fn preamble(&self, hashbang: Option<Atom<'a>>) -> String {
+        let mut preamble = String::new();
+        if let Some(hashbang) = hashbang {
+            let _ = writeln!(preamble, "#!{hashbang}");
+        }
+        preamble.push_str("\"use strict\";\n");
+        preamble.push_str("Object.defineProperty(exports, \"__esModule\", { value: true });\n");
+        if self.helpers.import_default {
+            preamble.push_str(IMPORT_DEFAULT_HELPER);
+        }
+        if self.helpers.import_star {
+            preamble.push_str(IMPORT_STAR_HELPER);
+        }
+        if self.helpers.export_star {
+            preamble.push_str(EXPORT_STAR_HELPER);
+        }
+        // exports are defined up front, like ESM bindings are, so modules
+        // with circular imports can see them before this module finishes
+        // loading.
+        for (exported, getter) in &self.exports {
+            let getter = self.import_names.get(getter).unwrap_or(getter);
+            let exported = serde_json::to_string(exported).unwrap();
+            let _ = writeln!(
+                preamble,
+                "Object.defineProperty(exports, {exported}, {{ enumerable: true, get: function () {{ return {getter}; }} }});"
+            );
+        }
+        preamble
+    }
+}
+
+impl<'a> ToCommonJs<'a> {
+    /// The module variable and property an identifier that refers to an
+    /// imported binding reads from.
+    fn import_for(&self, ident: &IdentifierReference<'a>) -> Option<&(String, String)> {
+        let reference_id = ident.reference_id.get()?;
+        let symbol_id = self.symbols.get_reference(reference_id).symbol_id()?;
+        self.imports.get(&symbol_id)
+    }
+
+    /// `_foo.bar` for an identifier that refers to an imported `bar`.
+    fn imported(&self, ident: &IdentifierReference<'a>) -> Option<Expression<'a>> {
+        let (module, property) = self.import_for(ident)?;
+        Some(self.member(self.ident(module), property))
+    }
+
+    /// `(0, _foo.bar)` for a callee that refers to an imported `bar`, so it
+    /// isn't called with the module as `this`.
+    fn imported_callee(&self, callee: &Expression<'a>) -> Option<Expression<'a>> {
+        let Expression::Identifier(ident) = callee else {
+            return None;
+        };
+        let member = self.imported(ident)?;
+        let zero = self
+            .ast
+            .expression_numeric_literal(SPAN, 0.0, "0", NumberBase::Decimal);
+        let mut expressions = self.ast.vec_with_capacity(2);
+        expressions.push(zero);
+        expressions.push(member);
+        Some(self.ast.expression_sequence(SPAN, expressions))
+    }
+}
+
+/// Rewrites references to imported bindings, after [`ToCommonJs::convert`]
+/// has removed the imports.
+impl<'a> VisitMut<'a> for ToCommonJs<'a> {
+    fn visit_expression(&mut self, it: &mut Expression<'a>) {
+        if let Expression::Identifier(ident) = it {
+            if let Some(member) = self.imported(ident) {
+                *it = member;
+                return;
+            }
+        }
+        walk_mut::walk_expression(self, it);
+    }
+
+    fn visit_call_expression(&mut self, it: &mut CallExpression<'a>) {
+        if let Some(callee) = self.imported_callee(&it.callee) {
+            it.callee = callee;
+        }
+        walk_mut::walk_call_expression(self, it);
+    }
+
+    fn visit_tagged_template_expression(&mut self, it: &mut TaggedTemplateExpression<'a>) {
+        if let Some(tag) = self.imported_callee(&it.tag) {
+            it.tag = tag;
+        }
+        walk_mut::walk_tagged_template_expression(self, it);
+    }
+
+    fn visit_object_property(&mut self, it: &mut ObjectProperty<'a>) {
+        // { foo } -> { foo: _foo.foo }
+        if let Expression::Identifier(ident) = &it.value {
+            if it.shorthand && self.import_for(ident).is_some() {
+                it.shorthand = false;
+            }
+        }
+        walk_mut::walk_object_property(self, it);
+    }
+
+    fn visit_jsx_element_name(&mut self, it: &mut JSXElementName<'a>) {
+        // <Foo /> -> <_foo.Foo />
+        if let JSXElementName::IdentifierReference(ident) = it {
+            if let Some((module, property)) = self.import_for(ident) {
+                if is_identifier_name(property) {
+                    let object = self.ast.jsx_member_expression_object_identifier_reference(
+                        SPAN,
+                        self.ast.atom(module),
+                    );
+                    let property = self.ast.jsx_identifier(SPAN, self.ast.atom(property));
+                    *it = self
+                        .ast
+                        .jsx_element_name_jsx_member_expression(ident.span, object, property);
+                    return;
+                }
+            }
+        }
+        walk_mut::walk_jsx_element_name(self, it);
+    }
+
+    fn visit_jsx_member_expression_object(&mut self, it: &mut JSXMemberExpressionObject<'a>) {
+        // <Foo.Bar /> -> <_foo.Foo.Bar />
+        if let JSXMemberExpressionObject::IdentifierReference(ident) = it {
+            if let Some((module, property)) = self.import_for(ident) {
+                if is_identifier_name(property) {
+                    let object = self.ast.jsx_member_expression_object_identifier_reference(
+                        SPAN,
+                        self.ast.atom(module),
+                    );
+                    let property = self.ast.jsx_identifier(SPAN, self.ast.atom(property));
+                    *it = JSXMemberExpressionObject::MemberExpression(
+                        self.ast
+                            .alloc(self.ast.jsx_member_expression(ident.span, object, property)),
+                    );
+                    return;
+                }
+            }
+        }
+        walk_mut::walk_jsx_member_expression_object(self, it);
+    }
+}
+
+const IMPORT_DEFAULT_HELPER: &str = "\
+function __importDefault(mod) {
+    return mod && mod.__esModule ? mod : { default: mod };
+}
+";
+
+const IMPORT_STAR_HELPER: &str = "\
+function __importStar(mod) {
+    if (mod && mod.__esModule) return mod;
+    var result = {};
+    if (mod != null) for (var k in mod) if (k !== \"default\" && Object.prototype.hasOwnProperty.call(mod, k)) result[k] = mod[k];
+    result.default = mod;
+    return result;
+}
+";
+
+const EXPORT_STAR_HELPER: &str = "\
+function __exportStar(mod, exports) {
+    Object.keys(mod).forEach(function (k) {
+        if (k === \"default\" || Object.prototype.hasOwnProperty.call(exports, k)) return;
+        Object.defineProperty(exports, k, { enumerable: true, get: function () { return mod[k]; } });
+    });
+}
+";
+
+/// `"./utils/foo-bar.js"` -> `_foo_bar`
+fn module_variable_name(source: &str) -> String {
+    let file = source
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let stem = file.split('.').next().unwrap_or_default();
+    let mut name = String::from("_");
+    for c in stem.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '$' {
+            name.push(c);
+        } else {
+            name.push('_');
+        }
+    }
+    if name == "_" {
+        name.push('m');
+    }
+    name
+}
+
+fn member_text(object: &str, property: &str) -> String {
+    if is_identifier_name(property) {
+        format!("{object}.{property}")
+    } else {
+        format!("{object}[{}]", serde_json::to_string(property).unwrap())
+    }
+}
+
+/// Names bound by an exported declaration.
+fn declared_names(declaration: &Declaration<'_>) -> Vec<String> {
+    let mut names = vec![];
+    match declaration {
+        Declaration::VariableDeclaration(decl) => {
+            for declarator in &decl.declarations {
+                pattern_names(&declarator.id.kind, &mut names);
+            }
+        }
+        Declaration::FunctionDeclaration(func) => {
+            names.extend(func.id.as_ref().map(|id| id.name.to_string()));
+        }
+        Declaration::ClassDeclaration(class) => {
+            names.extend(class.id.as_ref().map(|id| id.name.to_string()));
+        }
+        _ => {}
+    }
+    names
+}
+
+fn pattern_names(pattern: &BindingPatternKind<'_>, names: &mut Vec<String>) {
+    match pattern {
+        BindingPatternKind::BindingIdentifier(id) => names.push(id.name.to_string()),
+        BindingPatternKind::ObjectPattern(object) => {
+            for property in &object.properties {
+                pattern_names(&property.value.kind, names);
+            }
+            if let Some(rest) = &object.rest {
+                pattern_names(&rest.argument.kind, names);
+            }
+        }
+        BindingPatternKind::ArrayPattern(array) => {
+            for element in array.elements.iter().flatten() {
+                pattern_names(&element.kind, names);
+            }
+            if let Some(rest) = &array.rest {
+                pattern_names(&rest.argument.kind, names);
+            }
+        }
+        BindingPatternKind::AssignmentPattern(assignment) => {
+            pattern_names(&assignment.left.kind, names);
+        }
+    }
+}
+
+#[derive(Default)]
+struct NameCollector {
+    names: HashSet<String>,
+}
+
+impl<'a> Visit<'a> for NameCollector {
+    fn visit_binding_identifier(&mut self, it: &BindingIdentifier<'a>) {
+        self.names.insert(it.name.to_string());
+    }
+
+    fn visit_identifier_reference(&mut self, it: &IdentifierReference<'a>) {
+        self.names.insert(it.name.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use oxc::{allocator::Allocator, codegen::Codegen, parser::Parser, span::SourceType};
+
+    use super::{check_commonjs, to_commonjs, EsTarget, ModuleFormat};
+
+    const SOURCE_EXTS: [&str; 8] = ["ts", "tsx", "mts", "cts", "js", "jsx", "mjs", "cjs"];
+
+    /// `js_extension` for every source extension, as `(package type is
+    /// module, format, [ts, tsx, mts, cts, js, jsx, mjs, cjs])`.
+    const JS_EXTENSIONS: [(bool, ModuleFormat, [&str; 8]); 6] = [
+        (
+            true,
+            ModuleFormat::Preserve,
+            ["js", "js", "mjs", "cjs", "js", "js", "mjs", "cjs"],
+        ),
+        (
+            false,
+            ModuleFormat::Preserve,
+            ["js", "js", "mjs", "cjs", "js", "js", "mjs", "cjs"],
+        ),
+        (
+            true,
+            ModuleFormat::Esm,
+            ["js", "js", "mjs", "cjs", "js", "js", "mjs", "cjs"],
+        ),
+        (
+            false,
+            ModuleFormat::Esm,
+            ["mjs", "mjs", "mjs", "cjs", "mjs", "mjs", "mjs", "cjs"],
+        ),
+        (
+            true,
+            ModuleFormat::CommonJs,
+            ["cjs", "cjs", "mjs", "cjs", "cjs", "cjs", "mjs", "cjs"],
+        ),
+        (
+            false,
+            ModuleFormat::CommonJs,
+            ["js", "js", "mjs", "cjs", "js", "js", "mjs", "cjs"],
+        ),
+    ];
+
+    fn source(ext: &str) -> PathBuf {
+        Path::new("src/index").with_extension(ext)
+    }
+
+    #[test]
+    fn test_js_extension() {
+        for (is_module_package, format, expected) in JS_EXTENSIONS {
+            for (ext, expected) in SOURCE_EXTS.into_iter().zip(expected) {
+                assert_eq!(
+                    format.js_extension(&source(ext), is_module_package),
+                    expected,
+                    "{format} output for .{ext} with module package {is_module_package}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_dts_extension() {
+        for (is_module_package, format, expected) in JS_EXTENSIONS {
+            for (ext, js_ext) in SOURCE_EXTS.into_iter().zip(expected) {
+                let expected = match js_ext {
+                    "mjs" => "d.mts",
+                    "cjs" => "d.cts",
+                    _ => "d.ts",
+                };
+                assert_eq!(
+                    format.dts_extension(&source(ext), is_module_package),
+                    expected,
+                    "{format} declarations for .{ext} with module package {is_module_package}"
+                );
+            }
+        }
+    }
+
+    /// Convert `source` to CommonJS. The preamble is summarized as comments
+    /// listing the helpers it defines and the getter of each export.
+    fn convert(source: &str, target: EsTarget) -> Option<String> {
+        let allocator = Allocator::default();
+        let source_type = SourceType::mjs().with_jsx(true);
+        let mut program = Parser::new(&allocator, source, source_type).parse().program;
+        let preamble = to_commonjs(&allocator, &mut program, target)?;
+
+        let mut snapshot = String::new();
+        for line in preamble.lines() {
+            if let Some(helper) = line
+                .strip_prefix("function ")
+                .and_then(|line| line.split_once('('))
+            {
+                snapshot.push_str(&format!("// helper {}\n", helper.0));
+            } else if let Some(getter) = line
+                .strip_prefix("Object.defineProperty(exports, ")
+                .and_then(|line| line.strip_suffix("; } });"))
+            {
+                let (name, getter) = getter
+                    .split_once(", { enumerable: true, get: function () { return ")
+                    .unwrap();
+                snapshot.push_str(&format!("// exports[{name}] = {getter}\n"));
+            } else if line.starts_with("#!") {
+                snapshot.push_str(line);
+                snapshot.push('\n');
+            }
+        }
+        snapshot.push_str(&Codegen::new().build(&program).code);
+        Some(snapshot)
+    }
+
+    #[track_caller]
+    fn assert_converts(source: &str, expected: &str) {
+        assert_eq!(convert(source, EsTarget::ESNext).unwrap(), expected);
+    }
+
+    fn commonjs_errors(source: &str) -> Vec<String> {
+        let allocator = Allocator::default();
+        let program = Parser::new(&allocator, source, SourceType::mjs())
+            .parse()
+            .program;
+        check_commonjs(&program)
+            .into_iter()
+            .map(|error| error.message.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_check_commonjs() {
+        assert_eq!(
+            commonjs_errors("const url = import.meta.url;\nimport.meta.dirname;"),
+            ["`import.meta` cannot be compiled to CommonJS"]
+        );
+        assert_eq!(
+            commonjs_errors("await a;\nfor await (const x of xs) {}"),
+            ["Top-level `await` cannot be compiled to CommonJS"]
+        );
+        assert_eq!(
+            commonjs_errors("for await (const x of xs) {}"),
+            ["Top-level `await` cannot be compiled to CommonJS"]
+        );
+        assert!(commonjs_errors(
+            "async function f() { await a; for await (const x of xs) {} }\n\
+             const g = async () => await b;\n\
+             class C { async m() { await c; } }"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_no_module_syntax() {
+        assert_eq!(
+            convert("const x = require(\"foo\");", EsTarget::ESNext),
+            None
+        );
+    }
+
+    #[test]
+    fn test_side_effect_import() {
+        assert_converts("import \"foo\";", "require(\"foo\");\n");
+    }
+
+    #[test]
+    fn test_namespace_import() {
+        assert_converts(
+            "import * as ns from \"foo\";\nns.a;",
+            "// helper __importStar\nconst ns = __importStar(require(\"foo\"));\nns.a;\n",
+        );
+    }
+
+    #[test]
+    fn test_default_import() {
+        assert_converts(
+            "import foo from \"foo\";\nfoo();",
+            "// helper __importDefault\n\
+             const _foo = __importDefault(require(\"foo\"));\n\
+             (0, _foo.default)();\n",
+        );
+    }
+
+    #[test]
+    fn test_named_imports() {
+        assert_converts(
+            "import { a, b as c, \"d-e\" as f } from \"./foo.js\";\na;\nc();\nf;",
+            "const _foo = require(\"./foo.js\");\n_foo.a;\n(0, _foo.b)();\n_foo[\"d-e\"];\n",
+        );
+    }
+
+    #[test]
+    fn test_default_and_named_imports() {
+        assert_converts(
+            "import foo, { a } from \"foo\";\nfoo;\na;",
+            "// helper __importStar\n\
+             const _foo = __importStar(require(\"foo\"));\n\
+             _foo.default;\n\
+             _foo.a;\n",
+        );
+        assert_converts(
+            "import foo, * as ns from \"foo\";\nfoo;\nns.a;",
+            "// helper __importStar\n\
+             const ns = __importStar(require(\"foo\"));\n\
+             ns.default;\n\
+             ns.a;\n",
+        );
+    }
+
+    #[test]
+    fn test_imported_references() {
+        assert_converts(
+            "import { a, A } from \"foo\";\n\
+             const o = { a, b: a };\n\
+             a`x`;\n\
+             a?.();\n\
+             new A();\n\
+             typeof a;\n\
+             function f(a) { return a; }\n\
+             const el = <A.B />;\n\
+             const el2 = <A />;",
+            "const _foo = require(\"foo\");\n\
+             const o = {\n\ta: _foo.a,\n\tb: _foo.a\n};\n\
+             (0, _foo.a)`x`;\n\
+             (0, _foo.a)?.();\n\
+             new _foo.A();\n\
+             typeof _foo.a;\n\
+             function f(a) {\n\treturn a;\n}\n\
+             const el = <_foo.A.B />;\n\
+             const el2 = <_foo.A />;\n",
+        );
+    }
+
+    #[test]
+    fn test_exported_declarations() {
+        assert_converts(
+            "export const x = 1, { y } = {};\nexport function f() {}\nexport class C {}",
+            "// exports[\"x\"] = x\n\
+             // exports[\"y\"] = y\n\
+             // exports[\"f\"] = f\n\
+             // exports[\"C\"] = C\n\
+             const x = 1, { y } = {};\n\
+             function f() {}\n\
+             class C {}\n",
+        );
+    }
+
+    #[test]
+    fn test_export_specifiers() {
+        assert_converts(
+            "const x = 1;\nexport { x, x as \"a-b\" };",
+            "// exports[\"x\"] = x\n// exports[\"a-b\"] = x\nconst x = 1;\n",
+        );
+    }
+
+    #[test]
+    fn test_export_default() {
+        assert_converts(
+            "export default function () {}",
+            "// exports[\"default\"] = _default\nfunction _default() {}\n",
+        );
+        assert_converts(
+            "export default function foo() {}",
+            "// exports[\"default\"] = foo\nfunction foo() {}\n",
+        );
+        assert_converts(
+            "export default class {}",
+            "// exports[\"default\"] = _default\nclass _default {}\n",
+        );
+        assert_converts(
+            "const _default = 0;\nexport default 1 + 2;",
+            "// exports[\"default\"] = _default2\n\
+             const _default = 0;\n\
+             const _default2 = 1 + 2;\n",
+        );
+    }
+
+    #[test]
+    fn test_export_from() {
+        assert_converts(
+            "export { a, default as d } from \"foo\";",
+            "// helper __importDefault\n\
+             // exports[\"a\"] = _foo.a\n\
+             // exports[\"d\"] = __importDefault(_foo).default\n\
+             const _foo = require(\"foo\");\n",
+        );
+        assert_converts(
+            "export * from \"foo\";",
+            "// helper __exportStar\n__exportStar(require(\"foo\"), exports);\n",
+        );
+        assert_converts(
+            "export * as ns from \"foo\";",
+            "// helper __importStar\n\
+             // exports[\"ns\"] = _ns\n\
+             const _ns = __importStar(require(\"foo\"));\n",
+        );
+    }
+
+    #[test]
+    fn test_reexported_imports() {
+        assert_converts(
+            "import { a } from \"foo\";\nexport { a, a as b };\nexport default a;",
+            "// exports[\"a\"] = _foo.a\n\
+             // exports[\"b\"] = _foo.a\n\
+             // exports[\"default\"] = _default\n\
+             const _foo = require(\"foo\");\n\
+             const _default = _foo.a;\n",
+        );
+    }
+
+    #[test]
+    fn test_hashbang_and_directives() {
+        assert_converts(
+            "#!/usr/bin/env node\n\"use strict\";\nexport const x = 1;",
+            "#!/usr/bin/env node\n// exports[\"x\"] = x\nconst x = 1;\n",
+        );
+    }
+
+    #[test]
+    fn test_es5_uses_var() {
+        assert_eq!(
+            convert(
+                "import { a } from \"foo\";\nexport default a;",
+                EsTarget::ES5
+            )
+            .unwrap(),
+            "// exports[\"default\"] = _default\n\
+             var _foo = require(\"foo\");\n\
+             var _default = _foo.a;\n",
+        );
+    }
+
+    /// `a` and `b` import each other. Exports are getters defined before
+    /// anything is required, and imports are read when they're used, so each
+    /// module sees the other's bindings once it has finished loading.
+    #[test]
+    fn test_circular_imports() {
+        assert_converts(
+            "import { b } from \"./b.js\";\n\
+             export let count = 0;\n\
+             export function a() { count++; return b(); }",
+            "// exports[\"count\"] = count\n\
+             // exports[\"a\"] = a\n\
+             const _b = require(\"./b.js\");\n\
+             let count = 0;\n\
+             function a() {\n\tcount++;\n\treturn (0, _b.b)();\n}\n",
+        );
+        assert_converts(
+            "import { a, count } from \"./a.js\";\n\
+             export function b() { return count; }\n\
+             export const run = () => a();",
+            "// exports[\"b\"] = b\n\
+             // exports[\"run\"] = run\n\
+             const _a = require(\"./a.js\");\n\
+             function b() {\n\treturn _a.count;\n}\n\
+             const run = () => (0, _a.a)();\n",
+        );
+    }
+}
@@ -3,13 +3,44 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::options::DeclarationsOptions;
+use oxc::transformer::{JsxOptions, ReplaceGlobalDefinesConfig};
+
+use super::{EsTarget, ModuleFormat, PathAliases};
+use crate::options::{DeclarationsOptions, MinifyOptions};
 
 #[derive(Debug, Clone)]
 pub struct CompileOptions {
     root_dir: PathBuf,
+    /// Folder source files are compiled from. Relative imports of files
+    /// outside it are left alone.
+    src: PathBuf,
+    /// Does `package.json` have `"type": "module"`?
+    module_package: bool,
+    /// Keep JSX, writing `.jsx` files.
+    preserve_jsx: bool,
     /// Emit .d.ts files using isolatedDeclarations.
     declarations_options: Option<DeclarationsOptions>,
+    /// Generate source maps for compiled code.
+    source_maps: bool,
+    jsx: JsxOptions,
+    /// Expressions replaced with constant values, e.g. `process.env.NODE_ENV`
+    /// with `"production"`.
+    defines: Option<ReplaceGlobalDefinesConfig>,
+    minify: MinifyOptions,
+    /// Remove JSDoc comments from compiled code. Annotations like
+    /// `/* #__PURE__ */` are kept for bundlers.
+    strip_comments: bool,
+    /// Rewrite tsconfig `paths` aliases in imports into relative paths.
+    paths: Option<PathAliases>,
+    /// Variants of compiled code to produce from each source file.
+    outputs: Vec<OutputOptions>,
+}
+
+/// Settings that may differ between compiled variants of the same file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OutputOptions {
+    pub target: EsTarget,
+    pub module_format: ModuleFormat,
 }
 
 impl Default for CompileOptions {
@@ -26,17 +57,151 @@ impl CompileOptions {
         assert!(root_dir.is_absolute());
 
         Self {
+            src: root_dir.join("src"),
             root_dir,
+            module_package: false,
+            preserve_jsx: false,
             declarations_options: None,
+            source_maps: true,
+            jsx: JsxOptions::default(),
+            defines: None,
+            minify: MinifyOptions::default(),
+            strip_comments: false,
+            paths: None,
+            outputs: vec![OutputOptions::default()],
         }
     }
 
+    #[must_use]
+    pub fn with_src(mut self, value: PathBuf) -> Self {
+        self.src = value;
+        self
+    }
+
+    #[must_use]
+    pub fn with_module_package(mut self, yes: bool) -> Self {
+        self.module_package = yes;
+        self
+    }
+
+    #[must_use]
+    pub fn with_preserve_jsx(mut self, yes: bool) -> Self {
+        self.preserve_jsx = yes;
+        self
+    }
+
     #[must_use]
     pub fn with_d_ts(mut self, value: Option<DeclarationsOptions>) -> Self {
         self.declarations_options = value;
         self
     }
 
+    #[must_use]
+    pub fn with_source_maps(mut self, yes: bool) -> Self {
+        self.source_maps = yes;
+        self
+    }
+
+    #[must_use]
+    pub fn with_jsx(mut self, value: JsxOptions) -> Self {
+        self.jsx = value;
+        self
+    }
+
+    #[must_use]
+    pub fn with_defines(mut self, value: Option<ReplaceGlobalDefinesConfig>) -> Self {
+        self.defines = value;
+        self
+    }
+
+    #[must_use]
+    pub fn with_minify(mut self, value: MinifyOptions) -> Self {
+        self.minify = value;
+        self
+    }
+
+    #[must_use]
+    pub fn with_strip_comments(mut self, yes: bool) -> Self {
+        self.strip_comments = yes;
+        self
+    }
+
+    #[must_use]
+    pub fn with_paths(mut self, value: Option<PathAliases>) -> Self {
+        self.paths = value;
+        self
+    }
+
+    /// # Panics
+    /// If `value` is empty.
+    #[must_use]
+    pub fn with_outputs(mut self, value: Vec<OutputOptions>) -> Self {
+        assert!(!value.is_empty());
+        self.outputs = value;
+        self
+    }
+
+    #[inline]
+    pub fn src(&self) -> &Path {
+        &self.src
+    }
+
+    /// Extensions of code and declarations compiled from `source_path` to
+    /// `module_format`, e.g. `js` and `d.ts`.
+    pub fn extensions(
+        &self,
+        module_format: ModuleFormat,
+        source_path: &Path,
+    ) -> (&'static str, &'static str) {
+        let has_jsx = source_path
+            .extension()
+            .is_some_and(|ext| ext == "tsx" || ext == "jsx");
+        let js_ext = if self.preserve_jsx && has_jsx {
+            "jsx"
+        } else {
+            module_format.js_extension(source_path, self.module_package)
+        };
+        (
+            js_ext,
+            module_format.dts_extension(source_path, self.module_package),
+        )
+    }
+
+    #[inline]
+    pub fn outputs(&self) -> &[OutputOptions] {
+        &self.outputs
+    }
+
+    #[inline]
+    pub fn source_maps(&self) -> bool {
+        self.source_maps
+    }
+
+    #[inline]
+    pub fn jsx(&self) -> &JsxOptions {
+        &self.jsx
+    }
+
+    #[inline]
+    pub fn defines(&self) -> Option<&ReplaceGlobalDefinesConfig> {
+        self.defines.as_ref()
+    }
+
+    #[inline]
+    pub fn minify(&self) -> MinifyOptions {
+        self.minify
+    }
+
+    #[inline]
+    pub fn strip_comments(&self) -> bool {
+        self.strip_comments
+    }
+
+    #[inline]
+    pub fn paths(&self) -> Option<&PathAliases> {
+        self.paths.as_ref()
+    }
+
     #[inline]
     pub fn declarations_options(&self) -> Option<&DeclarationsOptions> {
         self.declarations_options.as_ref()
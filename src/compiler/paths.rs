@@ -0,0 +1,609 @@
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use oxc::{
+    allocator::Allocator,
+    ast::{
+        ast::{
+            Argument, CallExpression, ExportAllDeclaration, ExportNamedDeclaration, Expression,
+            ImportDeclaration, ImportExpression, Program, StringLiteral, TSExternalModuleReference,
+            TSImportType, TSLiteral, TSType,
+        },
+        visit::walk_mut,
+        AstBuilder, VisitMut,
+    },
+    parser::{Parser, ParserReturn},
+    span::{SourceType, Span},
+};
+
+use super::{CompileOptions, ModuleFormat};
+use crate::path::{normalize, relative_url};
+
+/// The source file each relative specifier in a file named and the target of
+/// each alias, or [`None`] if there wasn't one. These depend on what's on
+/// disk, so cached outputs are only reused if they haven't changed.
+pub type Resolutions = BTreeMap<String, Option<PathBuf>>;
+
+/// Source file extensions an import may resolve to, in the order they are
+/// tried.
+const SOURCE_EXTS: [&str; 8] = ["ts", "tsx", "mts", "cts", "js", "jsx", "mjs", "cjs"];
+
+/// Extensions of declaration files, which types-only imports may resolve to.
+const DECLARATION_EXTS: [&str; 3] = ["d.ts", "d.mts", "d.cts"];
+
+/// Import aliases from tsconfig `paths` and `baseUrl`, which are rewritten
+/// into relative paths because Node and bundlers don't know about them.
+#[derive(Debug, Clone)]
+pub struct PathAliases {
+    /// Folder `paths` targets are resolved from.
+    base_url: PathBuf,
+    /// Resolve specifiers that don't match a pattern from `base_url` too,
+    /// like `tsc` does when `baseUrl` is set.
+    resolve_from_base_url: bool,
+    /// `paths` patterns, which have at most one `*`, and their targets.
+    paths: Vec<(String, Vec<String>)>,
+    /// Only aliases for files in this folder are rewritten, since nothing else
+    /// ends up in `dist`.
+    src: PathBuf,
+}
+
+impl PathAliases {
+    pub fn new(
+        base_url: PathBuf,
+        resolve_from_base_url: bool,
+        paths: Vec<(String, Vec<String>)>,
+        src: PathBuf,
+    ) -> Self {
+        Self {
+            base_url,
+            resolve_from_base_url,
+            paths,
+            src,
+        }
+    }
+
+    /// Every setting that decides how aliases are rewritten, for the build
+    /// cache's fingerprint.
+    pub fn fingerprint(&self) -> impl Iterator<Item = String> + '_ {
+        let paths = self
+            .paths
+            .iter()
+            .map(|(pattern, targets)| format!("paths.{pattern}={}", targets.join("\n")));
+        [
+            format!("baseUrl={}", self.base_url.display()),
+            format!("resolveFromBaseUrl={}", self.resolve_from_base_url),
+            format!("src={}", self.src.display()),
+        ]
+        .into_iter()
+        .chain(paths)
+    }
+
+    /// The file or folder in `src` the alias `specifier` points at, or
+    /// [`None`] if it isn't an alias for a module in `src`.
+    fn resolve(&self, specifier: &str) -> Option<PathBuf> {
+        let candidates: Vec<_> = match self.find_pattern(specifier) {
+            Some((targets, wildcard)) => targets
+                .iter()
+                .map(|target| self.base_url.join(target.replacen('*', wildcard, 1)))
+                .collect(),
+            None if self.resolve_from_base_url => vec![self.base_url.join(specifier)],
+            None => return None,
+        };
+        candidates
+            .iter()
+            .map(|candidate| normalize(candidate))
+            .find(|candidate| is_module(candidate))
+            .filter(|target| target.starts_with(&self.src))
+    }
+
+    /// The targets of the pattern `specifier` matches and the text matched by
+    /// its `*`. Like `tsc`, exact patterns win, then the one with the longest
+    /// prefix.
+    fn find_pattern<'s>(&self, specifier: &'s str) -> Option<(&[String], &'s str)> {
+        if let Some((_, targets)) = self.paths.iter().find(|(pattern, _)| pattern == specifier) {
+            return Some((targets, ""));
+        }
+        self.paths
+            .iter()
+            .filter_map(|(pattern, targets)| {
+                let (prefix, suffix) = pattern.split_once('*')?;
+                let wildcard = specifier.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                Some((prefix.len(), targets.as_slice(), wildcard))
+            })
+            .max_by_key(|(prefix_len, ..)| *prefix_len)
+            .map(|(_, targets, wildcard)| (targets, wildcard))
+    }
+}
+
+/// Rewrite specifiers in imports, exports, dynamic imports, `require()` calls
+/// and `import()` types for `module_format`. Aliases become relative paths,
+/// and relative specifiers that name a source file point at the file it is
+/// compiled to, e.g. `./foo.js` or `./foo` at `./foo.mjs` when `foo.ts` is
+/// emitted as ESM in a CommonJS package, and `./lib` at `./lib/index.mjs`.
+/// Spans are left alone, so source maps still point at the original
+/// specifier.
+///
+/// What specifiers resolve to is looked up in `resolutions` first, and added
+/// to it.
+pub(super) fn rewrite_specifiers<'a>(
+    options: &CompileOptions,
+    module_format: ModuleFormat,
+    allocator: &'a Allocator,
+    program: &mut Program<'a>,
+    source_path: &Path,
+    resolutions: &mut Resolutions,
+) {
+    SpecifierRewriter::new(allocator, |specifier: &str| {
+        rewrite_specifier(options, module_format, specifier, source_path, resolutions)
+    })
+    .visit_program(program);
+}
+
+/// Rewrite specifiers in a hand-written declaration file the same way
+/// [`rewrite_specifiers`] does for compiled code. Only the specifiers are
+/// patched, so the rest of the file is kept as written. Returns [`None`] if
+/// nothing needs to change.
+pub fn rewrite_declaration_file(
+    options: &CompileOptions,
+    module_format: ModuleFormat,
+    source_path: &Path,
+    source_text: &str,
+) -> Option<String> {
+    let allocator = Allocator::default();
+    let source_type = SourceType::from_path(source_path).ok()?;
+    let ParserReturn {
+        mut program,
+        panicked,
+        ..
+    } = Parser::new(&allocator, source_text, source_type).parse();
+    if panicked {
+        return None;
+    }
+
+    let mut resolutions = Resolutions::new();
+    let mut rewriter = SpecifierRewriter::new(&allocator, |specifier: &str| {
+        rewrite_specifier(
+            options,
+            module_format,
+            specifier,
+            source_path,
+            &mut resolutions,
+        )
+    });
+    rewriter.visit_program(&mut program);
+    if rewriter.rewritten.is_empty() {
+        return None;
+    }
+
+    // keep the original quotes
+    rewriter.rewritten.sort_by_key(|(span, _)| span.start);
+    let mut rewritten = String::with_capacity(source_text.len());
+    let mut end = 0;
+    for (span, specifier) in rewriter.rewritten {
+        rewritten.push_str(&source_text[end..span.start as usize + 1]);
+        rewritten.push_str(&specifier);
+        end = span.end as usize - 1;
+    }
+    rewritten.push_str(&source_text[end..]);
+    Some(rewritten)
+}
+
+/// What replaces `specifier` in the file at `source_path` when it is emitted
+/// as `module_format`, or [`None`] if it stays as-is.
+fn rewrite_specifier(
+    options: &CompileOptions,
+    module_format: ModuleFormat,
+    specifier: &str,
+    source_path: &Path,
+    resolutions: &mut Resolutions,
+) -> Option<String> {
+    let mut resolve = |specifier: &str| {
+        resolutions
+            .entry(specifier.to_string())
+            .or_insert_with(|| resolve(options, specifier, source_path))
+            .clone()
+    };
+    let relative = if is_relative(specifier) {
+        specifier.to_string()
+    } else {
+        options.paths()?;
+        let target = resolve(specifier)?;
+        relative_specifier(source_path.parent()?, &target)
+    };
+    let rewritten = match resolve(&relative) {
+        Some(source_file) => {
+            let (js_ext, _) = options.extensions(module_format, &source_file);
+            let specified = normalize(&source_path.parent()?.join(&relative));
+            if source_file.parent() == Some(specified.as_path()) {
+                format!("{}/index.{js_ext}", relative.trim_end_matches('/'))
+            } else if has_source_ext(&specified) {
+                let (stem, _) = relative.rsplit_once('.')?;
+                format!("{stem}.{js_ext}")
+            } else {
+                format!("{relative}.{js_ext}")
+            }
+        }
+        None => relative,
+    };
+    (rewritten != specifier).then_some(rewritten)
+}
+
+/// What `specifier` in the file at `source_path` points at: the source file
+/// a relative specifier names, or the target of an alias.
+fn resolve(options: &CompileOptions, specifier: &str, source_path: &Path) -> Option<PathBuf> {
+    if is_relative(specifier) {
+        resolve_source_file(options, specifier, source_path)
+    } else {
+        options.paths()?.resolve(specifier)
+    }
+}
+
+/// Are `resolutions` from compiling `source_path` still what's on disk?
+pub fn resolutions_match(
+    options: &CompileOptions,
+    source_path: &Path,
+    resolutions: &Resolutions,
+) -> bool {
+    resolutions
+        .iter()
+        .all(|(specifier, resolved)| resolve(options, specifier, source_path) == *resolved)
+}
+
+/// The source file in `src` a relative specifier names. Like TypeScript,
+/// `./foo.js` may name `foo.ts`, `./foo` may name `foo.ts` and `./foo` or
+/// `./foo/` may name `foo/index.ts`.
+fn resolve_source_file(
+    options: &CompileOptions,
+    specifier: &str,
+    source_path: &Path,
+) -> Option<PathBuf> {
+    let path = normalize(&source_path.parent()?.join(specifier));
+    if !path.starts_with(options.src()) {
+        return None;
+    }
+    if has_source_ext(&path) {
+        if is_declaration(&path) {
+            return None;
+        }
+        if path.is_file() {
+            return Some(path);
+        }
+        let ext = path.extension()?.to_str()?;
+        return ts_extensions(ext)
+            .iter()
+            .map(|ext| path.with_extension(ext))
+            .find(|path| path.is_file());
+    }
+
+    let is_source_file = |path: &PathBuf| path.is_file() && !is_declaration(path);
+    // `./foo/` and `..` can only name a folder
+    let last = specifier.rsplit('/').next().unwrap_or_default();
+    let file = if matches!(last, "" | "." | "..") {
+        None
+    } else {
+        SOURCE_EXTS
+            .iter()
+            .map(|ext| with_ext(&path, ext))
+            .find(is_source_file)
+    };
+    file.or_else(|| {
+        SOURCE_EXTS
+            .iter()
+            .map(|ext| path.join("index").with_extension(ext))
+            .find(is_source_file)
+    })
+}
+
+/// Replaces module specifiers with what `rewrite` returns for them.
+struct SpecifierRewriter<'a, F> {
+    ast: AstBuilder<'a>,
+    rewrite: F,
+    /// Spans of rewritten specifiers, in source order, and what they were
+    /// replaced with.
+    rewritten: Vec<(Span, String)>,
+}
+
+impl<'a, F: FnMut(&str) -> Option<String>> SpecifierRewriter<'a, F> {
+    fn new(allocator: &'a Allocator, rewrite: F) -> Self {
+        Self {
+            ast: AstBuilder::new(allocator),
+            rewrite,
+            rewritten: vec![],
+        }
+    }
+
+    fn rewrite(&mut self, source: &mut StringLiteral<'a>) {
+        if let Some(specifier) = (self.rewrite)(&source.value) {
+            trace!("Rewriting '{}' to '{specifier}'", source.value);
+            source.value = self.ast.atom(&specifier);
+            self.rewritten.push((source.span, specifier));
+        }
+    }
+}
+
+impl<'a, F: FnMut(&str) -> Option<String>> VisitMut<'a> for SpecifierRewriter<'a, F> {
+    fn visit_import_declaration(&mut self, it: &mut ImportDeclaration<'a>) {
+        self.rewrite(&mut it.source);
+        walk_mut::walk_import_declaration(self, it);
+    }
+
+    fn visit_export_named_declaration(&mut self, it: &mut ExportNamedDeclaration<'a>) {
+        if let Some(source) = &mut it.source {
+            self.rewrite(source);
+        }
+        walk_mut::walk_export_named_declaration(self, it);
+    }
+
+    fn visit_export_all_declaration(&mut self, it: &mut ExportAllDeclaration<'a>) {
+        self.rewrite(&mut it.source);
+        walk_mut::walk_export_all_declaration(self, it);
+    }
+
+    fn visit_import_expression(&mut self, it: &mut ImportExpression<'a>) {
+        if let Expression::StringLiteral(source) = &mut it.source {
+            self.rewrite(source);
+        }
+        walk_mut::walk_import_expression(self, it);
+    }
+
+    fn visit_call_expression(&mut self, it: &mut CallExpression<'a>) {
+        // require("./foo")
+        if it.callee.is_specific_id("require") && it.arguments.len() == 1 {
+            if let Some(Argument::StringLiteral(source)) = it.arguments.first_mut() {
+                self.rewrite(source);
+            }
+        }
+        walk_mut::walk_call_expression(self, it);
+    }
+
+    fn visit_ts_import_type(&mut self, it: &mut TSImportType<'a>) {
+        if let TSType::TSLiteralType(literal) = &mut it.parameter {
+            if let TSLiteral::StringLiteral(source) = &mut literal.literal {
+                self.rewrite(source);
+            }
+        }
+        walk_mut::walk_ts_import_type(self, it);
+    }
+
+    fn visit_ts_external_module_reference(&mut self, it: &mut TSExternalModuleReference<'a>) {
+        self.rewrite(&mut it.expression);
+    }
+}
+
+fn is_relative(specifier: &str) -> bool {
+    specifier == "."
+        || specifier == ".."
+        || specifier.starts_with("./")
+        || specifier.starts_with("../")
+        || specifier.starts_with('/')
+}
+
+/// Does `path` resolve to a file, the way TypeScript resolves imports? Tries
+/// the path itself, then with each source or declaration extension, then
+/// `.js`-style extensions swapped for TypeScript ones, then `index` files.
+fn is_module(path: &Path) -> bool {
+    if path.is_file() {
+        return true;
+    }
+    if SOURCE_EXTS
+        .iter()
+        .chain(&DECLARATION_EXTS)
+        .any(|ext| with_ext(path, ext).is_file())
+    {
+        return true;
+    }
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    if ts_extensions(ext)
+        .iter()
+        .any(|ext| path.with_extension(ext).is_file())
+    {
+        return true;
+    }
+    path.is_dir()
+        && SOURCE_EXTS
+            .iter()
+            .chain(&DECLARATION_EXTS)
+            .any(|ext| path.join("index").with_extension(ext).is_file())
+}
+
+/// `path` with `.{ext}` appended, keeping any extension it already has.
+fn with_ext(path: &Path, ext: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
+
+fn has_source_ext(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SOURCE_EXTS.contains(&ext))
+}
+
+/// Is `path` a `.d.ts`, `.d.mts` or `.d.cts` file?
+fn is_declaration(path: &Path) -> bool {
+    path.file_stem()
+        .is_some_and(|stem| Path::new(stem).extension().is_some_and(|ext| ext == "d"))
+}
+
+/// TypeScript extensions a JavaScript extension in a specifier may stand for.
+fn ts_extensions(ext: &str) -> &'static [&'static str] {
+    match ext {
+        "js" => &["ts", "tsx"],
+        "jsx" => &["tsx"],
+        "mjs" => &["mts"],
+        "cjs" => &["cts"],
+        _ => &[],
+    }
+}
+
+/// A specifier for `to` that is relative to the folder `from`, always
+/// starting with `./` or `../`.
+fn relative_specifier(from: &Path, to: &Path) -> String {
+    let relative = relative_url(from, to);
+    if relative.is_empty() {
+        String::from(".")
+    } else if relative.starts_with("..") {
+        relative
+    } else {
+        format!("./{relative}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use super::*;
+
+    fn aliases(paths: &[(&str, &[&str])]) -> PathAliases {
+        let paths = paths
+            .iter()
+            .map(|(pattern, targets)| {
+                let targets = targets.iter().map(ToString::to_string).collect();
+                (pattern.to_string(), targets)
+            })
+            .collect();
+        PathAliases::new("/project".into(), false, paths, "/project/src".into())
+    }
+
+    #[test]
+    fn test_find_pattern() {
+        let aliases = aliases(&[
+            ("@/*", &["./src/*"]),
+            ("@/utils/*", &["./src/lib/utils/*", "./vendor/*"]),
+            ("@/config", &["./src/config.ts"]),
+            ("*.css", &["./styles/*.css"]),
+        ]);
+        let targets = |targets: &[&str]| targets.iter().map(ToString::to_string).collect();
+        let find = |specifier| {
+            aliases
+                .find_pattern(specifier)
+                .map(|(targets, wildcard)| (targets.to_vec(), wildcard))
+        };
+
+        assert_eq!(find("@/foo/bar"), Some((targets(&["./src/*"]), "foo/bar")));
+        // longest prefix wins
+        assert_eq!(
+            find("@/utils/date"),
+            Some((targets(&["./src/lib/utils/*", "./vendor/*"]), "date"))
+        );
+        // exact matches win over wildcards
+        assert_eq!(find("@/config"), Some((targets(&["./src/config.ts"]), "")));
+        assert_eq!(
+            find("theme.css"),
+            Some((targets(&["./styles/*.css"]), "theme"))
+        );
+        assert_eq!(find("react"), None);
+        assert_eq!(find("@"), None);
+    }
+
+    #[test]
+    fn test_relative_specifier() {
+        let specifier = |from: &str, to: &str| relative_specifier(Path::new(from), Path::new(to));
+        assert_eq!(specifier("/project/src", "/project/src/foo.ts"), "./foo.ts");
+        assert_eq!(
+            specifier("/project/src", "/project/src/lib/foo.ts"),
+            "./lib/foo.ts"
+        );
+        assert_eq!(
+            specifier("/project/src/lib/deep", "/project/src/foo.ts"),
+            "../../foo.ts"
+        );
+        assert_eq!(
+            specifier("/project/src/a", "/project/src/b/foo"),
+            "../b/foo"
+        );
+        assert_eq!(specifier("/project/src/lib", "/project/src/lib"), ".");
+    }
+
+    /// A project with a `src` folder containing `files`, which are empty.
+    fn project(files: &[&str]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = root.path().join("src").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        root
+    }
+
+    /// How specifiers in `src/index.ts` are rewritten for CommonJS output in
+    /// a `"type": "module"` package, which is emitted to `.cjs` files.
+    fn rewrite(options: &CompileOptions, specifier: &str) -> Option<String> {
+        let source_path = options.src().join("index.ts");
+        rewrite_specifier(
+            options,
+            ModuleFormat::CommonJs,
+            specifier,
+            &source_path,
+            &mut Resolutions::new(),
+        )
+    }
+
+    fn compile_options(root: &Path) -> CompileOptions {
+        let root = root.canonicalize().unwrap();
+        CompileOptions::new(root).with_module_package(true)
+    }
+
+    #[test]
+    fn test_rewrite_relative_specifiers() {
+        let root = project(&[
+            "index.ts",
+            "lib/math.ts",
+            "lib/esm.mts",
+            "lib/utils/index.ts",
+            "foo.config.ts",
+            "types.d.ts",
+        ]);
+        let options = compile_options(root.path());
+        let rewrite = |specifier| rewrite(&options, specifier);
+
+        assert_eq!(rewrite("./lib/math.js"), Some("./lib/math.cjs".into()));
+        assert_eq!(rewrite("./lib/math.ts"), Some("./lib/math.cjs".into()));
+        // extensionless specifiers get the extension the file is emitted with
+        assert_eq!(rewrite("./lib/math"), Some("./lib/math.cjs".into()));
+        assert_eq!(rewrite("./lib/esm"), Some("./lib/esm.mjs".into()));
+        assert_eq!(rewrite("./foo.config"), Some("./foo.config.cjs".into()));
+        // folders resolve to their index file
+        assert_eq!(rewrite("./lib/utils"), Some("./lib/utils/index.cjs".into()));
+        assert_eq!(
+            rewrite("./lib/utils/"),
+            Some("./lib/utils/index.cjs".into())
+        );
+        assert_eq!(rewrite("./lib/utils/index.cjs"), None);
+        // declarations, missing files and packages are left alone
+        assert_eq!(rewrite("./types"), None);
+        assert_eq!(rewrite("./types.d.ts"), None);
+        assert_eq!(rewrite("./missing"), None);
+        assert_eq!(rewrite("./lib"), None);
+        assert_eq!(rewrite("react"), None);
+    }
+
+    #[test]
+    fn test_rewrite_aliases() {
+        let root = project(&["index.ts", "lib/math.ts", "lib/utils/index.ts"]);
+        let options = compile_options(root.path());
+        let aliases = PathAliases::new(
+            options.src().parent().unwrap().to_path_buf(),
+            false,
+            vec![("@/*".to_string(), vec!["./src/*".to_string()])],
+            options.src().to_path_buf(),
+        );
+        let options = options.with_paths(Some(aliases));
+        let rewrite = |specifier| rewrite(&options, specifier);
+
+        assert_eq!(rewrite("@/lib/math"), Some("./lib/math.cjs".into()));
+        assert_eq!(rewrite("@/lib/math.js"), Some("./lib/math.cjs".into()));
+        assert_eq!(rewrite("@/lib/utils"), Some("./lib/utils/index.cjs".into()));
+        assert_eq!(rewrite("@/missing"), None);
+    }
+}
@@ -0,0 +1,527 @@
+use std::{fmt, str::FromStr};
+
+use oxc::{
+    ast::{
+        ast::{
+            ArrayAssignmentTarget, ArrayPattern, ArrowFunctionExpression, AwaitExpression,
+            BigIntLiteral, BindingRestElement, ChainExpression, Class, ForOfStatement,
+            FormalParameter, Function, IdentifierReference, MetaProperty, ObjectAssignmentTarget,
+            ObjectExpression, ObjectPattern, ObjectPropertyKind, PrivateIdentifier, Program,
+            PropertyDefinition, SpreadElement, StaticBlock, Super, TemplateLiteral,
+            VariableDeclaration, VariableDeclarationKind,
+        },
+        visit::walk,
+        Visit,
+    },
+    diagnostics::OxcDiagnostic,
+    semantic::ScopeFlags,
+    span::{GetSpan, Span},
+    transformer::TransformOptions,
+};
+
+/// ECMAScript version compiled code should run on.
+///
+/// Syntax newer than the target is lowered when oxc knows how to, and is
+/// reported as an error otherwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EsTarget {
+    ES5,
+    ES2015,
+    ES2016,
+    ES2017,
+    ES2018,
+    ES2019,
+    ES2020,
+    ES2021,
+    ES2022,
+    ES2023,
+    ES2024,
+    /// Keep all syntax as-is.
+    #[default]
+    ESNext,
+}
+
+impl FromStr for EsTarget {
+    type Err = String;
+
+    /// Parse a `compilerOptions.target` value. Matching is case-insensitive.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let target = match s.to_ascii_lowercase().as_str() {
+            // oxc can't do better than ES5 anyways
+            "es3" | "es5" => Self::ES5,
+            "es6" | "es2015" => Self::ES2015,
+            "es2016" => Self::ES2016,
+            "es2017" => Self::ES2017,
+            "es2018" => Self::ES2018,
+            "es2019" => Self::ES2019,
+            "es2020" => Self::ES2020,
+            "es2021" => Self::ES2021,
+            "es2022" => Self::ES2022,
+            "es2023" => Self::ES2023,
+            "es2024" => Self::ES2024,
+            "esnext" => Self::ESNext,
+            _ => return Err(format!("Unknown target '{s}'")),
+        };
+        Ok(target)
+    }
+}
+
+impl fmt::Display for EsTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::ES5 => "es5",
+            Self::ES2015 => "es2015",
+            Self::ES2016 => "es2016",
+            Self::ES2017 => "es2017",
+            Self::ES2018 => "es2018",
+            Self::ES2019 => "es2019",
+            Self::ES2020 => "es2020",
+            Self::ES2021 => "es2021",
+            Self::ES2022 => "es2022",
+            Self::ES2023 => "es2023",
+            Self::ES2024 => "es2024",
+            Self::ESNext => "esnext",
+        };
+        f.write_str(name)
+    }
+}
+
+impl EsTarget {
+    /// Enable every lowering pass needed for this target.
+    pub fn apply(self, options: &mut TransformOptions) {
+        if self < Self::ES2015 {
+            options.es2015.arrow_function = Some(Default::default());
+            options.regexp.sticky_flag = true;
+            options.regexp.unicode_flag = true;
+        }
+        if self < Self::ES2016 {
+            options.es2016.exponentiation_operator = true;
+        }
+        // object rest/spread isn't lowered: oxc emits calls to `babelHelpers`,
+        // which nothing defines. `check_syntax` reports it instead.
+        if self < Self::ES2018 {
+            options.regexp.dot_all_flag = true;
+            options.regexp.look_behind_assertions = true;
+            options.regexp.named_capture_groups = true;
+            options.regexp.unicode_property_escapes = true;
+        }
+        if self < Self::ES2019 {
+            options.es2019.optional_catch_binding = true;
+        }
+        if self < Self::ES2020 {
+            options.es2020.nullish_coalescing_operator = true;
+        }
+        if self < Self::ES2021 {
+            options.es2021.logical_assignment_operators = true;
+        }
+        if self < Self::ES2022 {
+            options.regexp.match_indices = true;
+        }
+        if self < Self::ESNext {
+            options.regexp.set_notation = true;
+        }
+    }
+
+    /// Find arrow functions in a program that is about to be transformed
+    /// which oxc can't turn into plain functions, because they use the
+    /// `arguments`, `super` or `new.target` of the enclosing function.
+    pub fn check_arrow_functions(self, program: &Program<'_>) -> Vec<OxcDiagnostic> {
+        if self >= Self::ES2015 {
+            return vec![];
+        }
+        let mut checker = ArrowFunctionChecker {
+            checker: SyntaxChecker::new(self),
+            in_arrow: false,
+        };
+        checker.visit_program(program);
+        checker.checker.errors
+    }
+
+    /// Find syntax in a transformed program that is too new for this target.
+    /// Each kind of syntax is only reported once.
+    pub fn check_syntax(self, program: &Program<'_>) -> Vec<OxcDiagnostic> {
+        if self == Self::ESNext {
+            return vec![];
+        }
+        let mut checker = SyntaxChecker::new(self);
+        checker.visit_program(program);
+        checker.errors
+    }
+}
+
+struct SyntaxChecker {
+    target: EsTarget,
+    /// How many functions deep the visitor is. `await` is only top-level at 0.
+    function_depth: usize,
+    reported: Vec<&'static str>,
+    errors: Vec<OxcDiagnostic>,
+}
+
+impl SyntaxChecker {
+    fn new(target: EsTarget) -> Self {
+        Self {
+            target,
+            function_depth: 0,
+            reported: vec![],
+            errors: vec![],
+        }
+    }
+
+    fn check(&mut self, since: EsTarget, syntax: &'static str, span: Span) {
+        if self.target >= since || self.reported.contains(&syntax) {
+            return;
+        }
+        self.reported.push(syntax);
+        self.errors.push(
+            OxcDiagnostic::error(format!("{syntax} cannot be compiled to {}", self.target))
+                .with_label(span)
+                .with_help(format!(
+                    "Set `target` to {since} or later to keep this syntax as-is"
+                )),
+        );
+    }
+}
+
+impl<'a> Visit<'a> for SyntaxChecker {
+    fn visit_arrow_function_expression(&mut self, it: &ArrowFunctionExpression<'a>) {
+        let header = Span::new(it.span.start, it.body.span.start);
+        // lowered for ES5, so only async arrows are left
+        self.check(EsTarget::ES2015, "Arrow functions", header);
+        if it.r#async {
+            self.check(EsTarget::ES2017, "Async functions", header);
+        }
+        self.function_depth += 1;
+        walk::walk_arrow_function_expression(self, it);
+        self.function_depth -= 1;
+    }
+
+    fn visit_function(&mut self, it: &Function<'a>, flags: ScopeFlags) {
+        let header = Span::new(it.span.start, it.params.span.end);
+        match (it.r#async, it.generator) {
+            (true, true) => self.check(EsTarget::ES2018, "Async generators", header),
+            (true, false) => self.check(EsTarget::ES2017, "Async functions", header),
+            (false, true) => self.check(EsTarget::ES2015, "Generators", header),
+            (false, false) => {}
+        }
+        self.function_depth += 1;
+        walk::walk_function(self, it, flags);
+        self.function_depth -= 1;
+    }
+
+    fn visit_await_expression(&mut self, it: &AwaitExpression<'a>) {
+        if self.function_depth == 0 {
+            self.check(EsTarget::ES2022, "Top-level `await`", it.span);
+        }
+        walk::walk_await_expression(self, it);
+    }
+
+    fn visit_class(&mut self, it: &Class<'a>) {
+        let header_end = it.id.as_ref().map_or(it.span.start, |id| id.span.end);
+        self.check(
+            EsTarget::ES2015,
+            "Classes",
+            Span::new(it.span.start, header_end),
+        );
+        walk::walk_class(self, it);
+    }
+
+    fn visit_property_definition(&mut self, it: &PropertyDefinition<'a>) {
+        self.check(EsTarget::ES2022, "Class fields", it.key.span());
+        walk::walk_property_definition(self, it);
+    }
+
+    fn visit_private_identifier(&mut self, it: &PrivateIdentifier<'a>) {
+        self.check(EsTarget::ES2022, "Private class members", it.span);
+    }
+
+    fn visit_static_block(&mut self, it: &StaticBlock<'a>) {
+        let header = Span::new(it.span.start, it.span.start + "static".len() as u32);
+        self.check(EsTarget::ES2022, "Class static blocks", header);
+        walk::walk_static_block(self, it);
+    }
+
+    fn visit_variable_declaration(&mut self, it: &VariableDeclaration<'a>) {
+        let keyword = |len: usize| Span::new(it.span.start, it.span.start + len as u32);
+        match it.kind {
+            VariableDeclarationKind::Let => {
+                self.check(EsTarget::ES2015, "`let` declarations", keyword(3));
+            }
+            VariableDeclarationKind::Const => {
+                self.check(EsTarget::ES2015, "`const` declarations", keyword(5));
+            }
+            VariableDeclarationKind::Using | VariableDeclarationKind::AwaitUsing => {
+                self.check(EsTarget::ESNext, "`using` declarations", it.span);
+            }
+            VariableDeclarationKind::Var => {}
+        }
+        walk::walk_variable_declaration(self, it);
+    }
+
+    fn visit_template_literal(&mut self, it: &TemplateLiteral<'a>) {
+        self.check(EsTarget::ES2015, "Template literals", it.span);
+        walk::walk_template_literal(self, it);
+    }
+
+    fn visit_object_expression(&mut self, it: &ObjectExpression<'a>) {
+        for property in &it.properties {
+            if let ObjectPropertyKind::SpreadProperty(spread) = property {
+                self.check(EsTarget::ES2018, "Object spread properties", spread.span);
+            }
+        }
+        walk::walk_object_expression(self, it);
+    }
+
+    fn visit_object_pattern(&mut self, it: &ObjectPattern<'a>) {
+        self.check(EsTarget::ES2015, "Destructuring", it.span);
+        if let Some(rest) = &it.rest {
+            self.check(EsTarget::ES2018, "Object rest properties", rest.span);
+        }
+        walk::walk_object_pattern(self, it);
+    }
+
+    fn visit_array_pattern(&mut self, it: &ArrayPattern<'a>) {
+        self.check(EsTarget::ES2015, "Destructuring", it.span);
+        walk::walk_array_pattern(self, it);
+    }
+
+    fn visit_object_assignment_target(&mut self, it: &ObjectAssignmentTarget<'a>) {
+        self.check(EsTarget::ES2015, "Destructuring", it.span);
+        if let Some(rest) = &it.rest {
+            self.check(EsTarget::ES2018, "Object rest properties", rest.span);
+        }
+        walk::walk_object_assignment_target(self, it);
+    }
+
+    fn visit_array_assignment_target(&mut self, it: &ArrayAssignmentTarget<'a>) {
+        self.check(EsTarget::ES2015, "Destructuring", it.span);
+        walk::walk_array_assignment_target(self, it);
+    }
+
+    fn visit_spread_element(&mut self, it: &SpreadElement<'a>) {
+        self.check(EsTarget::ES2015, "Spread syntax", it.span);
+        walk::walk_spread_element(self, it);
+    }
+
+    fn visit_binding_rest_element(&mut self, it: &BindingRestElement<'a>) {
+        self.check(EsTarget::ES2015, "Rest parameters", it.span);
+        walk::walk_binding_rest_element(self, it);
+    }
+
+    fn visit_formal_parameter(&mut self, it: &FormalParameter<'a>) {
+        if it.pattern.kind.is_assignment_pattern() {
+            self.check(EsTarget::ES2015, "Default parameters", it.span);
+        }
+        walk::walk_formal_parameter(self, it);
+    }
+
+    fn visit_for_of_statement(&mut self, it: &ForOfStatement<'a>) {
+        let header = Span::new(it.span.start, it.right.span().end);
+        if it.r#await {
+            self.check(EsTarget::ES2018, "`for await` loops", header);
+            if self.function_depth == 0 {
+                self.check(EsTarget::ES2022, "Top-level `await`", header);
+            }
+        } else {
+            self.check(EsTarget::ES2015, "`for...of` loops", header);
+        }
+        walk::walk_for_of_statement(self, it);
+    }
+
+    fn visit_meta_property(&mut self, it: &MetaProperty<'a>) {
+        if it.meta.name == "new" {
+            self.check(EsTarget::ES2015, "`new.target`", it.span);
+        } else {
+            self.check(EsTarget::ES2020, "`import.meta`", it.span);
+        }
+    }
+
+    fn visit_chain_expression(&mut self, it: &ChainExpression<'a>) {
+        self.check(EsTarget::ES2020, "Optional chaining", it.span);
+        walk::walk_chain_expression(self, it);
+    }
+
+    fn visit_big_int_literal(&mut self, it: &BigIntLiteral<'a>) {
+        self.check(EsTarget::ES2020, "BigInt literals", it.span);
+    }
+}
+
+/// Reports what oxc's arrow function transform doesn't handle yet. The
+/// transformed functions would silently use their own `arguments`, `super`
+/// and `new.target` instead.
+struct ArrowFunctionChecker {
+    checker: SyntaxChecker,
+    in_arrow: bool,
+}
+
+impl<'a> Visit<'a> for ArrowFunctionChecker {
+    fn visit_arrow_function_expression(&mut self, it: &ArrowFunctionExpression<'a>) {
+        let in_arrow = std::mem::replace(&mut self.in_arrow, true);
+        walk::walk_arrow_function_expression(self, it);
+        self.in_arrow = in_arrow;
+    }
+
+    fn visit_function(&mut self, it: &Function<'a>, flags: ScopeFlags) {
+        let in_arrow = std::mem::replace(&mut self.in_arrow, false);
+        walk::walk_function(self, it, flags);
+        self.in_arrow = in_arrow;
+    }
+
+    fn visit_class(&mut self, it: &Class<'a>) {
+        let in_arrow = std::mem::replace(&mut self.in_arrow, false);
+        walk::walk_class(self, it);
+        self.in_arrow = in_arrow;
+    }
+
+    fn visit_identifier_reference(&mut self, it: &IdentifierReference<'a>) {
+        if self.in_arrow && it.name == "arguments" {
+            self.checker
+                .check(EsTarget::ES2015, "`arguments` in arrow functions", it.span);
+        }
+    }
+
+    fn visit_super(&mut self, it: &Super) {
+        if self.in_arrow {
+            self.checker
+                .check(EsTarget::ES2015, "`super` in arrow functions", it.span);
+        }
+    }
+
+    fn visit_meta_property(&mut self, it: &MetaProperty<'a>) {
+        if self.in_arrow && it.meta.name == "new" {
+            self.checker
+                .check(EsTarget::ES2015, "`new.target` in arrow functions", it.span);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use oxc::{
+        allocator::Allocator, ast::ast::Program, diagnostics::OxcDiagnostic, parser::Parser,
+        span::SourceType, transformer::TransformOptions,
+    };
+
+    use super::EsTarget;
+
+    fn applied(target: EsTarget) -> TransformOptions {
+        let mut options = TransformOptions::default();
+        target.apply(&mut options);
+        options
+    }
+
+    /// Messages of the errors `check` reports for `source`.
+    fn errors(source: &str, check: impl Fn(&Program<'_>) -> Vec<OxcDiagnostic>) -> Vec<String> {
+        let allocator = Allocator::default();
+        let program = Parser::new(&allocator, source, SourceType::mjs())
+            .parse()
+            .program;
+        check(&program)
+            .into_iter()
+            .map(|error| error.message.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let esnext = applied(EsTarget::ESNext);
+        assert!(esnext.es2015.arrow_function.is_none());
+        assert!(!esnext.es2020.nullish_coalescing_operator);
+        assert!(!esnext.regexp.set_notation);
+
+        let es2020 = applied(EsTarget::ES2020);
+        assert!(!es2020.es2020.nullish_coalescing_operator);
+        assert!(es2020.es2021.logical_assignment_operators);
+        assert!(es2020.regexp.match_indices);
+
+        let es2017 = applied(EsTarget::ES2017);
+        assert!(es2017.es2019.optional_catch_binding);
+        assert!(es2017.regexp.named_capture_groups);
+        assert!(!es2017.es2016.exponentiation_operator);
+
+        let es5 = applied(EsTarget::ES5);
+        assert!(es5.es2015.arrow_function.is_some());
+        assert!(es5.es2016.exponentiation_operator);
+        assert!(es5.es2021.logical_assignment_operators);
+        assert!(es5.regexp.sticky_flag);
+    }
+
+    /// Lowering object rest/spread needs `babelHelpers`, so it's never
+    /// turned on.
+    #[test]
+    fn test_apply_keeps_object_rest_spread() {
+        for target in [EsTarget::ES5, EsTarget::ES2017, EsTarget::ESNext] {
+            assert!(applied(target).es2018.object_rest_spread.is_none());
+        }
+    }
+
+    #[test]
+    fn test_check_syntax() {
+        let source = "const o = { ...a, y: 2 };\nconst { x, ...rest } = o;\nx ?? y;";
+        assert_eq!(
+            errors(source, |p| EsTarget::ES2017.check_syntax(p)),
+            [
+                "Object spread properties cannot be compiled to es2017",
+                "Object rest properties cannot be compiled to es2017",
+            ]
+        );
+        assert!(errors(source, |p| EsTarget::ES2018.check_syntax(p)).is_empty());
+        assert!(errors(source, |p| EsTarget::ESNext.check_syntax(p)).is_empty());
+    }
+
+    #[test]
+    fn test_check_syntax_reports_each_kind_once() {
+        let source = "class A {}\nclass B { #x = 1; }\nlet a = 1n, b = 2n;";
+        assert_eq!(
+            errors(source, |p| EsTarget::ES5.check_syntax(p)),
+            [
+                "Classes cannot be compiled to es5",
+                "Class fields cannot be compiled to es5",
+                "Private class members cannot be compiled to es5",
+                "`let` declarations cannot be compiled to es5",
+                "BigInt literals cannot be compiled to es5",
+            ]
+        );
+        assert_eq!(
+            errors(source, |p| EsTarget::ES2020.check_syntax(p)),
+            [
+                "Class fields cannot be compiled to es2020",
+                "Private class members cannot be compiled to es2020",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_top_level_await() {
+        let source = "await a;\nasync function f() { await b; }\nconst g = async () => await c;";
+        assert_eq!(
+            errors(source, |p| EsTarget::ES2021.check_syntax(p)),
+            ["Top-level `await` cannot be compiled to es2021"]
+        );
+        assert!(errors(source, |p| EsTarget::ES2022.check_syntax(p)).is_empty());
+        assert_eq!(
+            errors("for await (const x of xs) {}", |p| EsTarget::ES2021
+                .check_syntax(p)),
+            ["Top-level `await` cannot be compiled to es2021"]
+        );
+        assert!(
+            errors("async function f() { for await (const x of xs) {} }", |p| {
+                EsTarget::ES2021.check_syntax(p)
+            })
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_check_arrow_functions() {
+        let source = "function f() { return () => arguments[0]; }\n\
+                      const g = () => function () { return arguments; };";
+        assert_eq!(
+            errors(source, |p| EsTarget::ES5.check_arrow_functions(p)),
+            ["`arguments` in arrow functions cannot be compiled to es5"]
+        );
+        assert!(errors(source, |p| EsTarget::ES2015.check_arrow_functions(p)).is_empty());
+        assert!(errors("const f = () => 1;", |p| EsTarget::ES5
+            .check_arrow_functions(p))
+        .is_empty());
+    }
+}
@@ -1,10 +1,16 @@
 mod cli;
 mod compiler;
 mod options;
+mod path;
 mod reporter;
 mod walk;
+mod watch;
 
-use std::{process::ExitCode, thread, time::Instant};
+use std::{
+    process::ExitCode,
+    thread,
+    time::{Duration, Instant},
+};
 
 extern crate pretty_env_logger;
 #[macro_use]
@@ -14,45 +20,151 @@ use miette::Result;
 use crate::{
     cli::{cli, CliOptions},
     options::OxbuildOptions,
-    reporter::{DiagnosticSender, Reporter},
+    reporter::{DiagnosticSender, Message, Reporter},
+    walk::WalkerBuilder,
+    watch::Watcher,
 };
 
-#[allow(clippy::print_stdout)]
 fn main() -> Result<ExitCode> {
     pretty_env_logger::init();
     let matches = cli();
-    let opts = CliOptions::new(matches).and_then(OxbuildOptions::new)?;
+    let cli_options = CliOptions::new(matches)?;
+    let format = cli_options.format;
+    let reports = cli_options.reports.clone();
+    let mut opts = OxbuildOptions::new(cli_options)?;
     let num_threads = opts.num_threads.get();
+    // start watching before the initial build so changes made during it aren't missed
+    let watcher = opts.watch.then(|| Watcher::new(&opts.src)).transpose()?;
 
-    let (mut reporter, report_sender) = Reporter::new();
+    let (mut reporter, report_sender) = Reporter::new(format, reports);
+    if let Some(diagnostics) = opts.config_diagnostics.take() {
+        report_sender
+            .send(Message::Diagnostics(diagnostics))
+            .unwrap();
+    }
+    let mut walker = WalkerBuilder::new(opts, report_sender.clone());
 
     let start = Instant::now();
-
-    let handle = thread::spawn(move || {
-        let mut walker = walk::WalkerBuilder::new(opts, report_sender.clone());
+    let (num_errors, num_warnings) = build(&mut reporter, &report_sender, || {
         walker.walk(num_threads);
-        report_sender.send(None).unwrap();
+    });
+    let (num_errors, num_warnings) = finish(
+        &mut walker,
+        &mut reporter,
+        &report_sender,
+        num_errors,
+        num_warnings,
+    );
+    reporter.write_reports()?;
+    print_summary(
+        &reporter,
+        start.elapsed(),
+        num_errors,
+        num_warnings,
+        num_threads,
+    );
+    let mut did_fail = num_errors > 0;
+
+    if let Some(watcher) = watcher {
+        reporter.print("Watching for changes...");
+        for changed in watcher {
+            let start = Instant::now();
+            let full_build = walker.needs_full_build();
+            let (num_errors, num_warnings) = build(&mut reporter, &report_sender, || {
+                if full_build {
+                    walker.walk(num_threads);
+                    // the walk doesn't see deleted files
+                    walker.rebuild(changed.into_iter().filter(|path| !path.exists()));
+                } else {
+                    walker.rebuild(changed);
+                }
+            });
+            let (num_errors, num_warnings) = finish(
+                &mut walker,
+                &mut reporter,
+                &report_sender,
+                num_errors,
+                num_warnings,
+            );
+            reporter.write_reports()?;
+            let threads_used = if full_build { num_threads } else { 1 };
+            print_summary(
+                &reporter,
+                start.elapsed(),
+                num_errors,
+                num_warnings,
+                threads_used,
+            );
+            did_fail = num_errors > 0;
+        }
+    }
+
+    Ok(ExitCode::from(u8::from(did_fail)))
+}
+
+/// Run a build job on a separate thread while reporting its diagnostics on
+/// this one. Returns the number of errors and warnings produced by the job.
+fn build<F>(reporter: &mut Reporter, sender: &DiagnosticSender, job: F) -> (usize, usize)
+where
+    F: FnOnce() + Send,
+{
+    let errors_before = reporter.errors_count();
+    let warnings_before = reporter.warnings_count();
+
+    thread::scope(|s| {
+        let sender = sender.clone();
+        s.spawn(move || {
+            job();
+            sender.send(Message::Done).unwrap();
+        });
+        reporter.run();
     });
 
-    reporter.run();
-    handle.join().unwrap();
+    (
+        reporter.errors_count() - errors_before,
+        reporter.warnings_count() - warnings_before,
+    )
+}
 
-    let duration = start.elapsed();
-    let num_errors = reporter.errors_count();
-    let num_warnings = reporter.warnings_count();
-    let did_fail = num_errors > 0;
+/// Write outputs held back by `noEmitOnError`, unless the build had errors.
+/// Returns the total number of errors and warnings, including any produced
+/// while writing.
+fn finish(
+    walker: &mut WalkerBuilder,
+    reporter: &mut Reporter,
+    sender: &DiagnosticSender,
+    num_errors: usize,
+    num_warnings: usize,
+) -> (usize, usize) {
+    if num_errors > 0 {
+        if !walker.finish(true) {
+            reporter.print("Nothing was written to dist because of errors (noEmitOnError).");
+        }
+        return (num_errors, num_warnings);
+    }
+    let (write_errors, write_warnings) = build(reporter, sender, || {
+        walker.finish(false);
+    });
+    (num_errors + write_errors, num_warnings + write_warnings)
+}
 
-    if num_errors > 0 && num_warnings > 0 {
-        println!(
+fn print_summary(
+    reporter: &Reporter,
+    duration: Duration,
+    num_errors: usize,
+    num_warnings: usize,
+    num_threads: usize,
+) {
+    let message = if num_errors > 0 || num_warnings > 0 {
+        format!(
             "Finished in {:2}ms with {num_errors} errors and {num_warnings} warnings using {num_threads} threads.",
             duration.as_millis()
-        );
+        )
     } else {
-        println!(
+        format!(
             "Finished in {:2}ms using {num_threads} threads.",
             duration.as_millis()
-        );
-    }
-
-    Ok(ExitCode::from(u8::from(did_fail)))
+        )
+    };
+    reporter.print(&message);
 }
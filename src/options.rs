@@ -1,15 +1,33 @@
-use crate::cli::{CliOptions, Root};
+mod config;
+mod glob;
+mod tsconfig;
+
+use crate::{
+    cli::{CliOptions, Root},
+    compiler::{EsTarget, ModuleFormat, PathAliases},
+};
 use std::{
+    collections::BTreeMap,
+    env, fmt,
     fs::{self},
     num::NonZeroUsize,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
 };
 
-use log::{debug, trace};
+use ignore::overrides::{Override, OverrideBuilder};
+use log::{debug, trace, warn};
 use miette::{IntoDiagnostic, Report, Result, WrapErr};
-// use package_json::{PackageJson, PackageJsonManager};
+use oxc::{
+    diagnostics::DiagnosticTuple,
+    transformer::{JsxOptions, JsxRuntime, ReplaceGlobalDefinesConfig},
+};
 use serde::Deserialize;
 
+use config::{MinifyConfig, OxbuildConfig};
+use tsconfig::TsConfig;
+
 #[derive(Debug)]
 pub struct OxbuildOptions {
     pub root: Root,
@@ -20,48 +38,237 @@ pub struct OxbuildOptions {
     pub isolated_declarations: Option<DeclarationsOptions>,
     /// Path to the folder containing source files to compile.
     pub src: PathBuf,
-    /// Path to output folder where compiled code will be written.
-    pub dist: PathBuf,
+    /// Output folders compiled code will be written to, and how code is
+    /// compiled for each of them. Never empty.
+    pub variants: Vec<OutputVariant>,
+    /// Which files in `src` to compile, from tsconfig `include`, `exclude`
+    /// and `files`, and `ignore` in the oxbuild config.
+    pub overrides: Override,
+    /// Files in `src` that are copied into `dist` as-is, from `assets` in the
+    /// oxbuild config. Defaults to JSON files.
+    pub assets: Override,
+    /// Generate source maps for compiled code. [`None`] disables them.
+    pub source_maps: Option<SourceMapOptions>,
+    pub jsx: JsxOptions,
+    /// Expressions replaced with constant values in compiled code, from
+    /// `--define`, `define` in the oxbuild config and the build mode.
+    pub defines: Vec<(String, String)>,
+    /// `defines`, parsed once for every file. [`None`] when there are none.
+    pub define_config: Option<ReplaceGlobalDefinesConfig>,
+    /// How compiled code is minified, from `--minify`, `minify` in the
+    /// oxbuild config and the build mode.
+    pub minify: MinifyOptions,
+    /// Remove JSDoc comments from compiled code. On in production mode.
+    pub strip_comments: bool,
+    /// Import aliases from tsconfig `paths` and `baseUrl`, which are rewritten
+    /// into relative paths. [`None`] when neither is set.
+    pub paths: Option<PathAliases>,
+    /// Keep JSX as-is and write compiled `.tsx` and `.jsx` files as `.jsx`,
+    /// from tsconfig `"jsx": "preserve"`.
+    pub preserve_jsx: bool,
+    /// Is `type` set to `"module"` in `package.json`? Decides whether `.js`
+    /// files are ESM or CommonJS.
+    pub module_package: bool,
+    /// Only write to `dist` once the whole build has succeeded, from tsconfig
+    /// `noEmitOnError`.
+    pub no_emit_on_error: bool,
     pub num_threads: NonZeroUsize,
+    /// Keep running after the initial build and recompile files as they change.
+    pub watch: bool,
+    /// Remove old outputs from each `dist` folder before building. [`None`]
+    /// leaves `dist` alone.
+    pub clean: Option<CleanMode>,
+    /// Folder where compiled outputs are cached between builds. [`None`]
+    /// disables the cache.
+    pub cache_dir: Option<PathBuf>,
+    /// Warnings found while reading the oxbuild config file. These should be
+    /// reported before building.
+    pub config_diagnostics: Option<DiagnosticTuple>,
     // package_json: PackageJson,
     // tsconfig: Option<PathBuf>, // TODO
 }
 
+/// One set of compiled outputs, e.g. an ESM build in `dist/esm` and a
+/// CommonJS build in `dist/cjs`.
+#[derive(Debug, Clone)]
+pub struct OutputVariant {
+    /// Path to output folder where compiled code will be written.
+    pub dist: PathBuf,
+    /// ECMAScript version to compile to.
+    pub target: EsTarget,
+    /// Module system compiled code uses.
+    pub module_format: ModuleFormat,
+}
+
+/// A build profile, which switches several settings together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// JSX development helpers and source maps that include source text.
+    Development,
+    /// Code is minified and comments are removed.
+    Production,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "development" => Ok(Self::Development),
+            "production" => Ok(Self::Production),
+            _ => Err(format!("Unsupported mode '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Development => "development",
+            Self::Production => "production",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How old outputs are removed from `dist`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CleanMode {
+    /// Delete each `dist` folder entirely.
+    All,
+    /// Only delete outputs whose source file no longer exists.
+    Stale,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SourceMapOptions {
+    /// Embed source maps in compiled code as data URLs instead of writing
+    /// `.map` files.
+    pub inline: bool,
+    /// Include the original source text in source maps.
+    pub inline_sources: bool,
+    /// Where debuggers should look for source files.
+    pub source_root: Option<String>,
+    /// Where debuggers should look for `.map` files, instead of next to
+    /// compiled code.
+    pub map_root: Option<String>,
+}
+
+/// Which minifications are applied to compiled code. All are off by default,
+/// and on in production mode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MinifyOptions {
+    /// Remove whitespace and comments.
+    pub whitespace: bool,
+    /// Rewrite code into shorter equivalents, e.g. `!0` for `true`.
+    pub compress: bool,
+    /// Rename local variables. Top-level names are kept.
+    pub mangle: bool,
+}
+
+impl MinifyOptions {
+    pub const ALL: Self = Self {
+        whitespace: true,
+        compress: true,
+        mangle: true,
+    };
+}
+
+impl FromStr for MinifyOptions {
+    type Err = String;
+
+    /// Parse `all`, `none` or a comma-separated list of `whitespace`,
+    /// `compress` and `mangle`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => return Ok(Self::ALL),
+            "none" => return Ok(Self::default()),
+            _ => {}
+        }
+        let mut options = Self::default();
+        for part in s.split(',') {
+            match part.trim() {
+                "whitespace" => options.whitespace = true,
+                "compress" => options.compress = true,
+                "mangle" => options.mangle = true,
+                part => {
+                    return Err(format!(
+                    "Unsupported minify option '{part}'. Expected whitespace, compress or mangle"
+                ))
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DeclarationsOptions {
     pub strip_internal: bool,
+    /// Emit `.d.ts.map` files, so "go to definition" lands in source files.
+    pub declaration_map: bool,
+    /// Report files whose declarations can't be generated as errors instead
+    /// of warnings.
+    pub fail_on_error: bool,
 }
 
 impl OxbuildOptions {
+    /// Resolve options from CLI flags, the oxbuild config file and
+    /// `tsconfig.json`, in that order of precedence.
     pub fn new(cli: CliOptions) -> Result<Self> {
         let CliOptions {
             root,
-            config: _config,
+            config,
             tsconfig,
             num_threads,
+            watch,
+            clean,
+            no_cache,
+            mode,
+            defines: cli_defines,
+            minify,
+            // only used by the reporter
+            format: _,
+            reports: _,
         } = cli;
 
         let tsconfig = root
             .resolve_file(tsconfig.as_ref(), ["tsconfig.json"])?
-            .map(|tsconfig_path| {
-                debug!("Reading tsconfig at '{}'", tsconfig_path.display());
-                fs::read_to_string(&tsconfig_path)
-                    .into_diagnostic()
-                    .with_context(|| {
-                        format!("Failed to read TSConfig at {}", tsconfig_path.display())
-                    })
-                    .and_then(TsConfig::parse)
-            })
+            .map(|tsconfig_path| TsConfig::load(&tsconfig_path))
             .transpose()?;
 
-        // TODO: config files
-        // let config = root.resolve_file(
-        //     config.as_ref(),
-        //     ["oxbuild.json", ".oxbuild.json", ".oxbuildrc"],
-        // )?;
+        let (config, config_diagnostics) = match config {
+            Some(config_path) => {
+                let (config, diagnostics) = OxbuildConfig::load(&config_path)?;
+                (Some(config), diagnostics)
+            }
+            None => (None, None),
+        };
 
         let co = tsconfig.as_ref().and_then(TsConfig::compiler_options);
-        let src = if let Some(root_dir) = co.and_then(|co| co.root_dir.as_ref()) {
+        // `None` leaves every setting a mode controls at its own default
+        let mode = mode
+            .or_else(|| config.as_ref().and_then(|c| c.mode))
+            .or_else(|| {
+                let node_env = env::var("NODE_ENV").ok()?;
+                let mode = node_env.parse().ok();
+                if mode.is_none() {
+                    debug!("Ignoring NODE_ENV '{node_env}'");
+                }
+                mode
+            });
+        if let Some(mode) = mode {
+            debug!("Building in {mode} mode");
+        }
+
+        let src = if let Some(src) = config.as_ref().and_then(OxbuildConfig::src) {
+            debug!("Resolving src from config file: '{}'", src.display());
+            src.canonicalize()
+                .into_diagnostic()
+                .with_context(|| format!("src directory does not exist: {}", src.display()))?
+        } else if let Some(root_dir) = co.and_then(|co| co.root_dir.as_ref()) {
             debug!(
                 "Resolving rootDir from tsconfig.json: '{}'",
                 root_dir.display()
@@ -77,13 +284,16 @@ impl OxbuildOptions {
         };
         if !src.is_dir() {
             return Err(Report::msg(format!(
-                "rootDir in tsconfig.json is not a directory: {}",
+                "src is not a directory: {}",
                 src.display()
             )));
         }
         trace!("src directory: '{}'", src.display());
 
-        let dist = if let Some(out_dir) = co.and_then(|co| co.out_dir.as_ref()) {
+        let dist = if let Some(dist) = config.as_ref().and_then(OxbuildConfig::dist) {
+            debug!("Resolving dist from config file: '{}'", dist.display());
+            dist
+        } else if let Some(out_dir) = co.and_then(|co| co.out_dir.as_ref()) {
             debug!(
                 "Resolving outDir from tsconfig.json: '{}'",
                 out_dir.display()
@@ -93,64 +303,437 @@ impl OxbuildOptions {
             debug!("Using default dist directory");
             root.join("dist").to_path_buf()
         };
-        // TODO: clean dist dir?
-        if !dist.exists() {
-            trace!("Creating dist directory at '{}'", dist.display());
-            fs::create_dir_all(&dist).into_diagnostic()?;
+
+        let asset_globs = config
+            .as_ref()
+            .and_then(OxbuildConfig::assets)
+            .unwrap_or_else(|| vec![glob::absolute_glob(&src, "**/*.json", false)]);
+        let mut assets = OverrideBuilder::new(glob::filesystem_root(&src));
+        for glob in &asset_globs {
+            assets
+                .add(glob)
+                .into_diagnostic()
+                .with_context(|| format!("Invalid asset pattern: '{glob}'"))?;
         }
-        assert!(dist.is_dir()); // FIXME: handle errors
-        let dist = dist
-            .canonicalize()
-            .into_diagnostic()
-            .wrap_err("Failed to canonicalize dist directory")?;
-        trace!("dist directory: '{}'", dist.display());
-
-        // no tsconfig means they're using JavaScript. We can't emit .d.ts files in that case.
-        let isolated_declarations = co.and_then(|co| {
-            co.isolated_declarations.unwrap_or(false).then(|| {
-                debug!("Enabling .d.ts emit");
-                DeclarationsOptions {
-                    strip_internal: co.strip_internal.unwrap_or(false),
-                }
+        let assets = assets.build().into_diagnostic()?;
+
+        let mut overrides = OverrideBuilder::new(glob::filesystem_root(&src));
+        if let Some(tsconfig) = tsconfig.as_ref() {
+            // without a tsconfig nothing is whitelisted, so assets aren't hidden
+            tsconfig.add_file_overrides(&mut overrides, &asset_globs)?;
+        }
+        if let Some(config) = config.as_ref() {
+            config.add_file_overrides(&mut overrides)?;
+        }
+        let overrides = overrides.build().into_diagnostic()?;
+
+        // no tsconfig means they're using JavaScript. We can't emit .d.ts files in that case,
+        // unless they explicitly ask for it.
+        let emit_declarations = config
+            .as_ref()
+            .and_then(|c| c.declarations)
+            .or_else(|| co.and_then(|co| co.isolated_declarations))
+            .unwrap_or(false);
+        let isolated_declarations = emit_declarations.then(|| {
+            debug!("Enabling .d.ts emit");
+            DeclarationsOptions {
+                strip_internal: co.and_then(|co| co.strip_internal).unwrap_or(false),
+                declaration_map: co.and_then(|co| co.declaration_map).unwrap_or(false),
+                fail_on_error: config
+                    .as_ref()
+                    .and_then(|c| c.fail_on_declaration_errors)
+                    .unwrap_or(true),
+            }
+        });
+
+        // unlike tsc, source maps are on unless something turns them off
+        let emit_source_maps = config
+            .as_ref()
+            .and_then(|c| c.source_maps)
+            .or_else(|| {
+                co.and_then(|co| match (co.source_map, co.inline_source_map) {
+                    (None, None) => None,
+                    (map, inline) => Some(map.unwrap_or(false) || inline.unwrap_or(false)),
+                })
             })
+            .unwrap_or(true);
+        let source_maps = emit_source_maps.then(|| SourceMapOptions {
+            inline: co.and_then(|co| co.inline_source_map).unwrap_or(false),
+            inline_sources: co
+                .and_then(|co| co.inline_sources)
+                .unwrap_or(mode == Some(Mode::Development)),
+            source_root: co.and_then(|co| co.source_root.clone()),
+            map_root: co.and_then(|co| co.map_root.clone()),
         });
 
+        let jsx_mode = co
+            .and_then(|co| co.jsx.as_deref())
+            .map(str::parse::<JsxMode>)
+            .transpose()
+            .map_err(Report::msg)?;
+        let jsx_config = config.as_ref().and_then(|c| c.jsx.as_ref());
+        let jsx_development = match mode {
+            Some(mode) => mode == Mode::Development,
+            None => jsx_mode == Some(JsxMode::ReactJsxDev),
+        };
+        // setting a runtime in the oxbuild config means JSX should be compiled
+        let jsx_mode = match (jsx_config.and_then(|c| c.runtime), jsx_mode) {
+            (Some(JsxRuntime::Classic), _) => Some(JsxMode::React),
+            (Some(JsxRuntime::Automatic), Some(JsxMode::ReactJsxDev)) => Some(JsxMode::ReactJsxDev),
+            (Some(JsxRuntime::Automatic), _) => Some(JsxMode::ReactJsx),
+            (None, mode) => mode,
+        };
+        let mut jsx = JsxOptions {
+            runtime: match jsx_mode {
+                Some(JsxMode::React) => JsxRuntime::Classic,
+                _ => JsxRuntime::Automatic,
+            },
+            development: jsx_config
+                .and_then(|c| c.development)
+                .unwrap_or(jsx_development),
+            import_source: jsx_config
+                .and_then(|c| c.import_source.clone())
+                .or_else(|| co.and_then(|co| co.jsx_import_source.clone())),
+            pragma: jsx_config
+                .and_then(|c| c.pragma.clone())
+                .or_else(|| co.and_then(|co| co.jsx_factory.clone())),
+            pragma_frag: jsx_config
+                .and_then(|c| c.pragma_frag.clone())
+                .or_else(|| co.and_then(|co| co.jsx_fragment_factory.clone())),
+            ..Default::default()
+        };
+        let keep_jsx = matches!(jsx_mode, Some(JsxMode::Preserve | JsxMode::ReactNative));
+        if keep_jsx {
+            jsx.jsx_plugin = false;
+            jsx.display_name_plugin = false;
+            jsx.development = false;
+        }
+        let preserve_jsx = jsx_mode == Some(JsxMode::Preserve);
+        debug!("JSX: {jsx_mode:?}, development: {}", jsx.development);
+
+        // later sources replace earlier ones with the same key
+        let mut defines = BTreeMap::new();
+        if let Some(mode) = mode {
+            defines.insert("process.env.NODE_ENV".to_string(), format!("\"{mode}\""));
+        }
+        if let Some(config_defines) = config.as_ref().and_then(|c| c.define.as_ref()) {
+            defines.extend(config_defines.clone());
+        }
+        defines.extend(cli_defines);
+        let defines: Vec<_> = defines.into_iter().collect();
+        let define_config = match ReplaceGlobalDefinesConfig::new(&defines) {
+            Ok(config) => (!defines.is_empty()).then_some(config),
+            Err(errors) => {
+                let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+                return Err(Report::msg(format!(
+                    "Invalid define: {}",
+                    messages.join(", ")
+                )));
+            }
+        };
+        trace!("Defines: {defines:?}");
+        let minify = minify
+            .or_else(|| {
+                config
+                    .as_ref()
+                    .and_then(|c| c.minify)
+                    .map(MinifyConfig::options)
+            })
+            .unwrap_or(if mode == Some(Mode::Production) {
+                MinifyOptions::ALL
+            } else {
+                MinifyOptions::default()
+            });
+        debug!("Minify: {minify:?}");
+        let strip_comments = mode == Some(Mode::Production);
+
+        // no target means no lowering, unlike tsc which defaults to ES5.
+        let target = config
+            .as_ref()
+            .and_then(|c| c.target.as_deref())
+            .or_else(|| co.and_then(|co| co.target.as_deref()))
+            .map(str::parse::<EsTarget>)
+            .transpose()
+            .map_err(Report::msg)?
+            .unwrap_or_default();
+        debug!("Compiling to {target}");
+
+        let module_package = is_module_package(&root);
+        let module_format = config
+            .as_ref()
+            .and_then(|c| c.module.as_deref())
+            .or_else(|| co.and_then(|co| co.module.as_deref()))
+            .map(|module| parse_module_format(module, module_package))
+            .transpose()?
+            .unwrap_or_default();
+        debug!("Emitting {module_format} modules");
+
+        // each variant falls back to the top-level settings
+        let variants = match config.as_ref().and_then(|c| c.variants.as_ref()) {
+            Some(variants) => variants
+                .iter()
+                .map(|variant| {
+                    let target = variant
+                        .target
+                        .as_deref()
+                        .map(str::parse)
+                        .transpose()
+                        .map_err(Report::msg)?
+                        .unwrap_or(target);
+                    let module_format = variant
+                        .module
+                        .as_deref()
+                        .map(|module| parse_module_format(module, module_package))
+                        .transpose()?
+                        .unwrap_or(module_format);
+                    Ok(OutputVariant {
+                        dist: create_dist(&variant.dist)?,
+                        target,
+                        module_format,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => vec![OutputVariant {
+                dist: create_dist(&dist)?,
+                target,
+                module_format,
+            }],
+        };
+        if variants.is_empty() {
+            return Err(Report::msg("`variants` must not be empty"));
+        }
+        for (i, variant) in variants.iter().enumerate() {
+            if variants[..i].iter().any(|other| other.dist == variant.dist) {
+                return Err(Report::msg(format!(
+                    "Several variants write to the same dist directory: {}",
+                    variant.dist.display()
+                )));
+            }
+            trace!(
+                "dist directory: '{}' ({}, {})",
+                variant.dist.display(),
+                variant.target,
+                variant.module_format
+            );
+        }
+
+        let clean = clean.or_else(|| {
+            config
+                .as_ref()
+                .and_then(|c| c.clean)
+                .and_then(|clean| clean.mode())
+        });
+        if clean.is_some() {
+            let canonical_src = src.canonicalize().into_diagnostic()?;
+            if let Some(variant) = variants
+                .iter()
+                .find(|variant| canonical_src.starts_with(&variant.dist))
+            {
+                return Err(Report::msg(format!(
+                    "Refusing to clean {} because it contains the src directory {}",
+                    variant.dist.display(),
+                    src.display()
+                )));
+            }
+        }
+
+        let use_cache = !no_cache && config.as_ref().and_then(|c| c.cache).unwrap_or(true);
+        let cache_dir = use_cache.then(|| cache_dir(&root)).flatten();
+
+        let paths = match co {
+            Some(co) if co.base_url.is_some() || co.paths.is_some() => {
+                let base_url = co
+                    .base_url
+                    .clone()
+                    .or_else(|| co.paths_dir.clone())
+                    .unwrap();
+                let patterns = co.paths.clone().unwrap_or_default().into_iter().collect();
+                debug!("Resolving import aliases from '{}'", base_url.display());
+                Some(PathAliases::new(
+                    base_url,
+                    co.base_url.is_some(),
+                    patterns,
+                    src.canonicalize().into_diagnostic()?,
+                ))
+            }
+            _ => None,
+        };
+
+        let no_emit_on_error = co.and_then(|co| co.no_emit_on_error).unwrap_or(false);
+
+        let num_threads = match num_threads.or_else(|| config.as_ref().and_then(|c| c.threads)) {
+            Some(n) => n,
+            None => {
+                thread::available_parallelism().into_diagnostic().with_context(|| "Failed to determine number of threads available. Please provide this explicitly using -t,--threads <n>")?
+            }
+        };
+
         Ok(Self {
             root,
             isolated_declarations,
             src,
-            dist,
+            variants,
+            overrides,
+            assets,
+            source_maps,
+            jsx,
+            defines,
+            define_config,
+            minify,
+            strip_comments,
+            paths,
+            preserve_jsx,
+            module_package,
+            no_emit_on_error,
             num_threads,
+            watch,
+            clean,
+            cache_dir,
+            config_diagnostics,
         })
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct TsConfig {
-    // TODO: tsconfig extends
-    compiler_options: Option<TsConfigCompilerOptions>,
+/// How JSX is compiled, from tsconfig `jsx`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsxMode {
+    /// Keep JSX and write `.jsx` files.
+    Preserve,
+    /// Keep JSX, but write `.js` files.
+    ReactNative,
+    /// Classic runtime, e.g. `React.createElement`.
+    React,
+    /// Automatic runtime, e.g. `react/jsx-runtime`.
+    ReactJsx,
+    /// Automatic runtime with development helpers.
+    ReactJsxDev,
+}
+
+impl FromStr for JsxMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mode = match s.to_ascii_lowercase().as_str() {
+            "preserve" => Self::Preserve,
+            "react-native" => Self::ReactNative,
+            "react" => Self::React,
+            "react-jsx" => Self::ReactJsx,
+            "react-jsxdev" => Self::ReactJsxDev,
+            _ => return Err(format!("Unsupported jsx option '{s}'")),
+        };
+        Ok(mode)
+    }
+}
+
+/// `node16` and `nodenext` mean whatever node thinks `.js` files are, which
+/// depends on the package type.
+fn parse_module_format(module: &str, module_package: bool) -> Result<ModuleFormat> {
+    if module.eq_ignore_ascii_case("node16") || module.eq_ignore_ascii_case("nodenext") {
+        return Ok(if module_package {
+            ModuleFormat::Esm
+        } else {
+            ModuleFormat::CommonJs
+        });
+    }
+    module.parse().map_err(Report::msg)
 }
-impl TsConfig {
-    fn compiler_options(&self) -> Option<&TsConfigCompilerOptions> {
-        self.compiler_options.as_ref()
+
+fn create_dist(dist: &Path) -> Result<PathBuf> {
+    if !dist.exists() {
+        trace!("Creating dist directory at '{}'", dist.display());
+        fs::create_dir_all(dist).into_diagnostic()?;
+    }
+    if !dist.is_dir() {
+        return Err(Report::msg(format!(
+            "dist is not a directory: {}",
+            dist.display()
+        )));
     }
+    dist.canonicalize()
+        .into_diagnostic()
+        .wrap_err("Failed to canonicalize dist directory")
 }
 
-#[derive(Debug, Default, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct TsConfigCompilerOptions {
-    // TODO: parse more fields as needed
-    root_dir: Option<PathBuf>,
-    out_dir: Option<PathBuf>,
-    strip_internal: Option<bool>,
-    isolated_declarations: Option<bool>,
+/// Where outputs compiled for the package in `root` are cached, inside the
+/// nearest `node_modules`. Packages in a workspace usually share a hoisted
+/// one, so each gets its own folder, named after its path from there.
+/// [`None`] when there's no `node_modules` to put the cache in.
+fn cache_dir(root: &Path) -> Option<PathBuf> {
+    let Some(dir) = root
+        .ancestors()
+        .find(|dir| dir.join("node_modules").is_dir())
+    else {
+        warn!(
+            "Not caching compiled outputs because there's no node_modules folder in '{}' or above it",
+            root.display()
+        );
+        return None;
+    };
+    let package: Vec<_> = root
+        .strip_prefix(dir)
+        .unwrap()
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    let name = if package.is_empty() {
+        String::from("+")
+    } else {
+        package.join("+")
+    };
+    Some(dir.join("node_modules/.cache/oxbuild").join(name))
 }
 
-impl TsConfig {
-    pub fn parse(mut source_text: String) -> Result<Self> {
-        json_strip_comments::strip(&mut source_text).unwrap();
+fn is_module_package(root: &Root) -> bool {
+    // only `type` matters, so other fields may be missing or malformed
+    #[derive(Deserialize)]
+    struct PackageJson {
+        r#type: Option<String>,
+    }
+
+    let Some(package_json) = root.find(["package.json"]) else {
+        return false;
+    };
+    let parsed = fs::read_to_string(&package_json)
+        .into_diagnostic()
+        .and_then(|text| serde_json::from_str::<PackageJson>(&text).into_diagnostic());
+    match parsed {
+        Ok(package_json) => package_json.r#type.as_deref() == Some("module"),
+        Err(e) => {
+            debug!("Failed to read '{}': {e}", package_json.display());
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_cache_dir() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        assert_eq!(cache_dir(root), None);
+
+        fs::create_dir_all(root.join("node_modules")).unwrap();
+        let cache = root.join("node_modules/.cache/oxbuild");
+        assert_eq!(cache_dir(root), Some(cache.join("+")));
+        assert_eq!(
+            cache_dir(&root.join("packages/app")),
+            Some(cache.join("packages+app"))
+        );
+        assert_eq!(
+            cache_dir(&root.join("packages/@org/lib")),
+            Some(cache.join("packages+@org+lib"))
+        );
 
-        serde_json::from_str(&source_text).into_diagnostic()
+        // a package's own `node_modules` is used when it has one
+        fs::create_dir_all(root.join("packages/app/node_modules")).unwrap();
+        assert_eq!(
+            cache_dir(&root.join("packages/app")),
+            Some(root.join("packages/app/node_modules/.cache/oxbuild/+"))
+        );
     }
 }
@@ -0,0 +1,591 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use ignore::overrides::OverrideBuilder;
+use log::debug;
+use miette::{IntoDiagnostic, Result, WrapErr};
+use oxc::{
+    diagnostics::{DiagnosticService, DiagnosticTuple, Error, NamedSource, OxcDiagnostic},
+    span::Span,
+    transformer::JsxRuntime,
+};
+use serde::{
+    de::{self, value::MapAccessDeserializer, IntoDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use super::{glob::absolute_glob, CleanMode, MinifyOptions, Mode};
+
+/// Contents of an `oxbuild.json`, `.oxbuild.json` or `.oxbuildrc` file.
+///
+/// Config files are JSON, but may contain comments and trailing commas. All
+/// fields are optional. Settings here take precedence over `tsconfig.json`,
+/// while CLI flags take precedence over both.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct OxbuildConfig {
+    /// Directory containing the config file. Relative paths are resolved
+    /// against it.
+    #[serde(skip)]
+    dir: PathBuf,
+    /// Lets editors validate the config file. Ignored by oxbuild.
+    #[serde(rename = "$schema")]
+    _schema: Option<String>,
+    /// Folder containing source files. Overrides `rootDir`.
+    src: Option<PathBuf>,
+    /// Folder compiled files are written to. Overrides `outDir`.
+    dist: Option<PathBuf>,
+    /// Emit `.d.ts` files. Overrides `isolatedDeclarations`.
+    pub declarations: Option<bool>,
+    /// Fail the build when declarations can't be generated for a file.
+    /// Defaults to `true`; when `false`, these are reported as warnings.
+    pub fail_on_declaration_errors: Option<bool>,
+    /// Emit `.js.map` files. Overrides `sourceMap` and `inlineSourceMap`.
+    /// Defaults to `true`.
+    pub source_maps: Option<bool>,
+    pub jsx: Option<JsxConfig>,
+    /// Build profile, `"development"` or `"production"`. Overrides
+    /// `NODE_ENV`.
+    pub mode: Option<Mode>,
+    /// Expressions to replace with constant values, e.g.
+    /// `{ "__DEV__": "false" }`. Values are JavaScript, so strings must be
+    /// quoted.
+    pub define: Option<BTreeMap<String, String>>,
+    /// Minify compiled code: `true`, `false`, or an object choosing
+    /// `whitespace`, `compress` and `mangle`.
+    pub minify: Option<MinifyConfig>,
+    /// ECMAScript version to compile to, e.g. `"es2020"`.
+    pub target: Option<String>,
+    /// Module format to emit, `"esm"`, `"commonjs"` or `"preserve"`.
+    /// Overrides `module`.
+    pub module: Option<String>,
+    /// Compile every file several times, e.g. once as ESM and once as
+    /// CommonJS. Replaces `dist` when set.
+    pub variants: Option<Vec<VariantConfig>>,
+    /// Glob patterns for files that are copied into `dist` as-is. Replaces
+    /// the default, which copies JSON files.
+    assets: Option<Vec<String>>,
+    /// Glob patterns for source files that should not be compiled.
+    #[serde(default)]
+    ignore: Vec<String>,
+    /// Number of threads to use.
+    pub threads: Option<NonZeroUsize>,
+    /// Remove old outputs before building: `true` or `"all"` deletes `dist`,
+    /// `"stale"` only deletes outputs without a source file.
+    pub clean: Option<CleanConfig>,
+    /// Reuse outputs from previous builds for unchanged files. Defaults to
+    /// `true`.
+    pub cache: Option<bool>,
+}
+
+/// `true`, `false`, `"all"` or `"stale"`. Not `untagged`, which would also
+/// accept objects like `{ "stale": null }`.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum CleanConfig {
+    Enabled(bool),
+    Mode(CleanMode),
+}
+
+impl CleanConfig {
+    pub fn mode(self) -> Option<CleanMode> {
+        match self {
+            Self::Enabled(enabled) => enabled.then_some(CleanMode::All),
+            Self::Mode(mode) => Some(mode),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CleanConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CleanVisitor;
+
+        impl<'de> Visitor<'de> for CleanVisitor {
+            type Value = CleanConfig;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("true, false, \"all\" or \"stale\"")
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(CleanConfig::Enabled(v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                CleanMode::deserialize(v.into_deserializer()).map(CleanConfig::Mode)
+            }
+        }
+
+        deserializer.deserialize_any(CleanVisitor)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum MinifyConfig {
+    Enabled(bool),
+    Options(MinifyPassesConfig),
+}
+
+/// The object form of `minify`. Omitted fields are on.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub(super) struct MinifyPassesConfig {
+    whitespace: Option<bool>,
+    compress: Option<bool>,
+    mangle: Option<bool>,
+}
+
+impl MinifyConfig {
+    pub fn options(self) -> MinifyOptions {
+        match self {
+            Self::Enabled(true) => MinifyOptions::ALL,
+            Self::Enabled(false) => MinifyOptions::default(),
+            Self::Options(MinifyPassesConfig {
+                whitespace,
+                compress,
+                mangle,
+            }) => MinifyOptions {
+                whitespace: whitespace.unwrap_or(true),
+                compress: compress.unwrap_or(true),
+                mangle: mangle.unwrap_or(true),
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MinifyConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MinifyVisitor;
+
+        impl<'de> Visitor<'de> for MinifyVisitor {
+            type Value = MinifyConfig;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a boolean or an object with `whitespace`, `compress` and `mangle`")
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(MinifyConfig::Enabled(v))
+            }
+
+            // `untagged` would buffer the object, hiding unknown keys in it
+            // from `serde_ignored`
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                MinifyPassesConfig::deserialize(MapAccessDeserializer::new(map))
+                    .map(MinifyConfig::Options)
+            }
+        }
+
+        deserializer.deserialize_any(MinifyVisitor)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct JsxConfig {
+    pub runtime: Option<JsxRuntime>,
+    /// Use development helpers, like `jsxDEV` and `__source`.
+    pub development: Option<bool>,
+    /// Module the automatic runtime imports JSX helpers from.
+    pub import_source: Option<String>,
+    /// Function called for JSX elements in the classic runtime.
+    pub pragma: Option<String>,
+    /// Function called for JSX fragments in the classic runtime.
+    pub pragma_frag: Option<String>,
+}
+
+/// An entry in `variants`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct VariantConfig {
+    /// Folder this variant is written to. After loading, this is absolute.
+    pub dist: PathBuf,
+    /// Overrides the top-level `module`.
+    pub module: Option<String>,
+    /// Overrides the top-level `target`.
+    pub target: Option<String>,
+}
+
+impl OxbuildConfig {
+    /// Read a config file. Unknown options are not an error; they are returned
+    /// as warnings pointing into the file.
+    pub fn load(path: &Path) -> Result<(Self, Option<DiagnosticTuple>)> {
+        debug!("Reading oxbuild config at '{}'", path.display());
+        let source_text = fs::read_to_string(path)
+            .into_diagnostic()
+            .with_context(|| format!("Failed to read config file at {}", path.display()))?;
+        let mut json = source_text.clone();
+        // replaces comments with whitespace, so offsets into `json` are still
+        // valid offsets into `source_text`.
+        json_strip_comments::strip(&mut json).unwrap();
+
+        let mut unknown = vec![];
+        let mut de = serde_json::Deserializer::from_str(&json);
+        let config = serde_ignored::deserialize(&mut de, |path| unknown.push(key_path(&path)))
+            .and_then(|config: Self| de.end().map(|()| config));
+        let mut config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                let offset = line_col_to_offset(&json, e.line(), e.column());
+                let error = OxcDiagnostic::error(format!("Invalid config file: {e}"))
+                    .with_label(Span::new(offset, offset));
+                let source = NamedSource::new(path.to_string_lossy(), source_text);
+                return Err(Error::new(error).with_source_code(source));
+            }
+        };
+        config.dir = path.parent().unwrap().to_path_buf();
+        for variant in config.variants.iter_mut().flatten() {
+            variant.dist = config.dir.join(&variant.dist);
+        }
+
+        let warnings: Vec<_> = unknown
+            .into_iter()
+            .map(|key_path| {
+                let key = key_path.join(".");
+                let warning = OxcDiagnostic::warn(format!("Unknown option '{key}'"))
+                    .with_help("Check the spelling of this option or remove it");
+                match find_key(&json, &key_path) {
+                    Some(span) => warning.with_label(span),
+                    None => warning,
+                }
+            })
+            .collect();
+        let diagnostics = (!warnings.is_empty())
+            .then(|| DiagnosticService::wrap_diagnostics(path, &source_text, warnings));
+
+        Ok((config, diagnostics))
+    }
+
+    /// Absolute path to the `src` folder, if set.
+    pub fn src(&self) -> Option<PathBuf> {
+        self.src.as_ref().map(|src| self.dir.join(src))
+    }
+
+    /// Absolute path to the `dist` folder, if set.
+    pub fn dist(&self) -> Option<PathBuf> {
+        self.dist.as_ref().map(|dist| self.dir.join(dist))
+    }
+
+    /// Absolute glob patterns for assets, if set.
+    pub fn assets(&self) -> Option<Vec<String>> {
+        self.assets.as_ref().map(|globs| {
+            globs
+                .iter()
+                .map(|glob| absolute_glob(&self.dir, glob, false))
+                .collect()
+        })
+    }
+
+    /// Add `ignore` patterns to `builder`. These take precedence over
+    /// patterns from `tsconfig.json`, so add them last.
+    pub fn add_file_overrides(&self, builder: &mut OverrideBuilder) -> Result<()> {
+        for glob in &self.ignore {
+            builder
+                .add(&format!("!{}", absolute_glob(&self.dir, glob, false)))
+                .into_diagnostic()
+                .with_context(|| format!("Invalid ignore pattern in config file: '{glob}'"))?;
+        }
+        Ok(())
+    }
+}
+
+fn key_path(path: &serde_ignored::Path) -> Vec<String> {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => vec![],
+        Path::Seq { parent, index } => {
+            let mut keys = key_path(parent);
+            keys.push(index.to_string());
+            keys
+        }
+        Path::Map { parent, key } => {
+            let mut keys = key_path(parent);
+            keys.push(key.clone());
+            keys
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => key_path(parent),
+    }
+}
+
+/// `serde_json` reports 1-based lines and columns, but labels need offsets.
+fn line_col_to_offset(text: &str, line: usize, column: usize) -> u32 {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let offset = (line_start + column.saturating_sub(1)).min(text.len());
+    u32::try_from(offset).unwrap_or(u32::MAX)
+}
+
+/// Find the span of the object key at `path` in a comment-free JSON document.
+/// Numeric path segments index into arrays.
+fn find_key(json: &str, path: &[String]) -> Option<Span> {
+    let mut scanner = KeyScanner {
+        json: json.as_bytes(),
+        pos: 0,
+    };
+    scanner.find(path)
+}
+
+/// Just enough of a JSON scanner to locate object keys. Assumes the document
+/// is valid, since `serde_json` has already parsed it.
+struct KeyScanner<'a> {
+    json: &'a [u8],
+    pos: usize,
+}
+
+impl KeyScanner<'_> {
+    fn find(&mut self, path: &[String]) -> Option<Span> {
+        let (first, rest) = path.split_first()?;
+        self.skip_whitespace();
+        match self.peek()? {
+            b'{' => self.find_in_object(first, rest),
+            b'[' => self.find_in_array(first.parse().ok()?, rest),
+            _ => None,
+        }
+    }
+
+    fn find_in_object(&mut self, key: &str, rest: &[String]) -> Option<Span> {
+        loop {
+            self.pos += 1; // '{' or ','
+            self.skip_whitespace();
+            if self.peek()? == b'}' {
+                return None;
+            }
+            let (start, end) = self.string()?;
+            let found: String = serde_json::from_slice(&self.json[start..end]).ok()?;
+            self.skip_whitespace();
+            self.pos += 1; // ':'
+            if found == key {
+                if rest.is_empty() {
+                    return Self::span(start, end);
+                }
+                return self.find(rest);
+            }
+            self.skip_value()?;
+            self.skip_whitespace();
+            if self.peek()? != b',' {
+                return None;
+            }
+        }
+    }
+
+    fn find_in_array(&mut self, index: usize, rest: &[String]) -> Option<Span> {
+        for i in 0.. {
+            self.pos += 1; // '[' or ','
+            self.skip_whitespace();
+            if self.peek()? == b']' {
+                return None;
+            }
+            if i == index {
+                if rest.is_empty() {
+                    let start = self.pos;
+                    self.skip_value()?;
+                    return Self::span(start, self.pos);
+                }
+                return self.find(rest);
+            }
+            self.skip_value()?;
+            self.skip_whitespace();
+            if self.peek()? != b',' {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn span(start: usize, end: usize) -> Option<Span> {
+        Some(Span::new(
+            u32::try_from(start).ok()?,
+            u32::try_from(end).ok()?,
+        ))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.json.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume a string, returning its span including quotes.
+    fn string(&mut self) -> Option<(usize, usize)> {
+        let start = self.pos;
+        self.pos += 1;
+        loop {
+            match self.peek()? {
+                b'\\' => self.pos += 2,
+                b'"' => {
+                    self.pos += 1;
+                    return Some((start, self.pos));
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        let mut depth = 0usize;
+        loop {
+            match self.peek()? {
+                b'"' => {
+                    self.string()?;
+                }
+                b'{' | b'[' => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                b'}' | b']' if depth == 0 => return Some(()),
+                b'}' | b']' => {
+                    depth -= 1;
+                    self.pos += 1;
+                }
+                b',' if depth == 0 => return Some(()),
+                _ => self.pos += 1,
+            }
+            if depth == 0 && matches!(self.json.get(self.pos - 1), Some(b'"' | b'}' | b']')) {
+                return Some(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    const JSON: &str = r#"{
+  "src": "lib",
+  "typo": true,
+  "jsx": { "runtime": "automatic", "nope": [1, {"a": "}"}] },
+  "variants": [
+    { "dist": "dist/esm", "module": "esm" },
+    { "dist": "dist/cjs", "modul": "cjs" }
+  ]
+}"#;
+
+    fn find<'a>(path: &[&str]) -> Option<&'a str> {
+        let path: Vec<_> = path.iter().map(ToString::to_string).collect();
+        find_key(JSON, &path).map(|span| &JSON[span.start as usize..span.end as usize])
+    }
+
+    #[test]
+    fn test_find_key() {
+        assert_eq!(find(&["typo"]), Some("\"typo\""));
+        assert_eq!(find(&["jsx", "nope"]), Some("\"nope\""));
+        assert_eq!(find(&["missing"]), None);
+        assert_eq!(find(&["jsx", "missing"]), None);
+        assert_eq!(find(&[]), None);
+    }
+
+    #[test]
+    fn test_find_key_in_array() {
+        assert_eq!(find(&["variants", "1", "modul"]), Some("\"modul\""));
+        assert_eq!(find(&["variants", "0", "module"]), Some("\"module\""));
+        assert_eq!(find(&["variants", "0", "modul"]), None);
+        assert_eq!(find(&["variants", "2", "modul"]), None);
+        assert_eq!(find(&["variants", "x", "modul"]), None);
+        assert_eq!(find(&["jsx", "nope", "1", "a"]), Some("\"a\""));
+        assert_eq!(find(&["jsx", "nope", "0"]), Some("1"));
+    }
+
+    #[test]
+    fn test_key_path() {
+        use serde_ignored::Path;
+        let variants = Path::Map {
+            parent: &Path::Root,
+            key: "variants".to_string(),
+        };
+        let variant = Path::Seq {
+            parent: &variants,
+            index: 1,
+        };
+        let modul = Path::Map {
+            parent: &variant,
+            key: "modul".to_string(),
+        };
+        assert_eq!(key_path(&modul), ["variants", "1", "modul"]);
+    }
+
+    #[test]
+    fn test_line_col_to_offset() {
+        let text = "ab\ncd\n\nef";
+        assert_eq!(line_col_to_offset(text, 1, 1), 0);
+        assert_eq!(line_col_to_offset(text, 1, 2), 1);
+        assert_eq!(line_col_to_offset(text, 2, 1), 3);
+        assert_eq!(line_col_to_offset(text, 3, 1), 6);
+        assert_eq!(line_col_to_offset(text, 4, 2), 8);
+        // out of range positions are clamped to the end
+        assert_eq!(line_col_to_offset(text, 9, 9), text.len() as u32);
+        // serde_json reports column 0 at the start of a line
+        assert_eq!(line_col_to_offset(text, 2, 0), 3);
+    }
+
+    /// Load `json` as a config file, returning the config and the messages
+    /// of its warnings.
+    fn load(json: &str) -> Result<(OxbuildConfig, Vec<String>)> {
+        let mut file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        file.write_all(json.as_bytes()).unwrap();
+        let (config, diagnostics) = OxbuildConfig::load(file.path())?;
+        let warnings = diagnostics
+            .map(|(_, warnings)| warnings.iter().map(ToString::to_string).collect())
+            .unwrap_or_default();
+        Ok((config, warnings))
+    }
+
+    #[test]
+    fn test_load_unknown_keys() {
+        let (config, warnings) = load(JSON).unwrap();
+        assert_eq!(config.src, Some(PathBuf::from("lib")));
+        assert_eq!(
+            warnings,
+            [
+                "Unknown option 'typo'",
+                "Unknown option 'jsx.nope'",
+                "Unknown option 'variants.1.modul'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_minify() {
+        let options = |json| load(json).unwrap().0.minify.map(MinifyConfig::options);
+        assert_eq!(options(r#"{ "minify": true }"#), Some(MinifyOptions::ALL));
+        assert_eq!(
+            options(r#"{ "minify": false }"#),
+            Some(MinifyOptions::default())
+        );
+        assert_eq!(
+            options(r#"{ "minify": { "mangle": false } }"#),
+            Some(MinifyOptions {
+                mangle: false,
+                ..MinifyOptions::ALL
+            })
+        );
+
+        let (_, warnings) = load(r#"{ "minify": { "whitespac": false } }"#).unwrap();
+        assert_eq!(warnings, ["Unknown option 'minify.whitespac'"]);
+        assert!(load(r#"{ "minify": "yes" }"#).is_err());
+    }
+
+    #[test]
+    fn test_load_clean() {
+        let mode = |json| load(json).unwrap().0.clean.and_then(CleanConfig::mode);
+        assert_eq!(mode(r#"{ "clean": true }"#), Some(CleanMode::All));
+        assert_eq!(mode(r#"{ "clean": false }"#), None);
+        assert_eq!(mode(r#"{ "clean": "stale" }"#), Some(CleanMode::Stale));
+        assert!(load(r#"{ "clean": "some" }"#).is_err());
+        assert!(load(r#"{ "clean": { "stale": null } }"#).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::path::normalize;
+
+/// The root of the file system `path` lives on. Globs created by
+/// [`absolute_glob`] are relative to this.
+pub(super) fn filesystem_root(path: &Path) -> &Path {
+    path.ancestors().last().unwrap_or(path)
+}
+
+/// Resolve a path pattern relative to `dir` into a glob anchored at the root
+/// of the file system. `.` and `..` segments are resolved lexically. When
+/// `literal` is `true`, `pattern` is a plain path and gets escaped.
+pub(super) fn absolute_glob(dir: &Path, pattern: &str, literal: bool) -> String {
+    let dir: PathBuf = dir
+        .components()
+        .map(|component| match component {
+            Component::Normal(segment) => escape_glob(&segment.to_string_lossy()).into(),
+            component => component.as_os_str().to_os_string(),
+        })
+        .collect();
+    let pattern: Vec<String> = pattern
+        .split('/')
+        .map(|segment| match segment {
+            "." | ".." => segment.to_string(),
+            segment if literal => escape_glob(segment),
+            segment => segment.to_string(),
+        })
+        .collect();
+
+    let segments: Vec<_> = normalize(&dir.join(pattern.join("/")))
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(segment) => Some(segment.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    format!("/{}", segments.join("/"))
+}
+
+fn escape_glob(segment: &str) -> String {
+    let mut escaped = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '{' | '}') {
+            escaped.extend(['[', c, ']']);
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// `tsc` treats an `include` pattern as a directory when its last segment has
+/// neither a wildcard nor a file extension.
+pub(super) fn is_directory_pattern(glob: &str) -> bool {
+    let last = glob.rsplit('/').next().unwrap_or(glob);
+    !last.contains(['*', '?', '.', ']'])
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_absolute_glob() {
+        let dir = Path::new("/project/packages/app");
+        assert_eq!(
+            absolute_glob(dir, "src/**/*.ts", false),
+            "/project/packages/app/src/**/*.ts"
+        );
+        assert_eq!(
+            absolute_glob(dir, "./src", false),
+            "/project/packages/app/src"
+        );
+        assert_eq!(
+            absolute_glob(dir, "../shared/*.json", false),
+            "/project/packages/shared/*.json"
+        );
+        assert_eq!(absolute_glob(dir, ".", false), "/project/packages/app");
+        assert_eq!(absolute_glob(dir, "/abs/*.ts", false), "/abs/*.ts");
+        assert_eq!(absolute_glob(dir, "../../../..", false), "/");
+    }
+
+    #[test]
+    fn test_absolute_glob_escapes() {
+        let dir = Path::new("/project/[app]");
+        assert_eq!(
+            absolute_glob(dir, "src/*.ts", false),
+            "/project/[[]app[]]/src/*.ts"
+        );
+        assert_eq!(
+            absolute_glob(dir, "src/{a}*.ts", true),
+            "/project/[[]app[]]/src/[{]a[}][*].ts"
+        );
+    }
+
+    #[test]
+    fn test_is_directory_pattern() {
+        assert!(is_directory_pattern("/project/src"));
+        assert!(is_directory_pattern("/project/src/lib"));
+        assert!(!is_directory_pattern("/project/src/**/*"));
+        assert!(!is_directory_pattern("/project/src/index.ts"));
+        assert!(!is_directory_pattern("/project/src/file?"));
+        assert!(!is_directory_pattern("/project/[[]app[]]"));
+    }
+}
@@ -0,0 +1,531 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ignore::overrides::OverrideBuilder;
+use log::{debug, trace};
+use miette::{IntoDiagnostic, Report, Result, WrapErr};
+use serde::Deserialize;
+
+use super::glob::{absolute_glob, is_directory_pattern};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct TsConfig {
+    /// Directory containing the top-level tsconfig file.
+    #[serde(skip)]
+    dir: PathBuf,
+    extends: Option<Extends>,
+    compiler_options: Option<TsConfigCompilerOptions>,
+    /// Glob patterns for files to compile. After loading, these are absolute.
+    include: Option<Vec<String>>,
+    /// Glob patterns for files to skip, even if they match `include`. After
+    /// loading, these are absolute.
+    exclude: Option<Vec<String>>,
+    /// Files to compile, regardless of `include` and `exclude`. After loading,
+    /// these are absolute globs that match exactly one path.
+    files: Option<Vec<String>>,
+}
+
+/// `extends` may be a single path or, since TypeScript 5.0, a list of paths.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Extends {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct TsConfigCompilerOptions {
+    // TODO: parse more fields as needed
+    pub root_dir: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    pub strip_internal: Option<bool>,
+    pub isolated_declarations: Option<bool>,
+    pub declaration_map: Option<bool>,
+    pub target: Option<String>,
+    pub module: Option<String>,
+    pub source_map: Option<bool>,
+    pub inline_source_map: Option<bool>,
+    pub inline_sources: Option<bool>,
+    pub source_root: Option<String>,
+    pub map_root: Option<String>,
+    pub no_emit_on_error: Option<bool>,
+    pub jsx: Option<String>,
+    pub jsx_import_source: Option<String>,
+    pub jsx_factory: Option<String>,
+    pub jsx_fragment_factory: Option<String>,
+    /// Folder non-relative imports are resolved from. After loading, this is
+    /// absolute.
+    pub base_url: Option<PathBuf>,
+    /// Import aliases, e.g. `"@/*": ["./src/*"]`.
+    pub paths: Option<BTreeMap<String, Vec<String>>>,
+    /// Directory of the tsconfig file that declared `paths`. Targets are
+    /// resolved from here when there's no `baseUrl`.
+    #[serde(skip)]
+    pub paths_dir: Option<PathBuf>,
+}
+
+impl TsConfig {
+    /// Read a tsconfig file and resolve its `extends` chain.
+    ///
+    /// Relative paths in `compilerOptions` are resolved against the directory
+    /// of the file that declared them, so everything returned is absolute.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_inner(path, &mut Vec::new())
+    }
+
+    pub fn compiler_options(&self) -> Option<&TsConfigCompilerOptions> {
+        self.compiler_options.as_ref()
+    }
+
+    fn load_inner(path: &Path, seen: &mut Vec<PathBuf>) -> Result<Self> {
+        debug!("Reading tsconfig at '{}'", path.display());
+        let path = path
+            .canonicalize()
+            .into_diagnostic()
+            .with_context(|| format!("Failed to read TSConfig at {}", path.display()))?;
+        if seen.contains(&path) {
+            return Err(Report::msg(format!(
+                "Circularity detected while resolving 'extends' in {}",
+                path.display()
+            )));
+        }
+
+        let mut config = fs::read_to_string(&path)
+            .into_diagnostic()
+            .and_then(Self::parse)
+            .with_context(|| format!("Failed to read TSConfig at {}", path.display()))?;
+        let dir = path.parent().unwrap();
+        config.dir = dir.to_path_buf();
+        if let Some(co) = config.compiler_options.as_mut() {
+            co.resolve_paths(dir);
+        }
+        for (patterns, literal) in [
+            (&mut config.include, false),
+            (&mut config.exclude, false),
+            (&mut config.files, true),
+        ] {
+            for pattern in patterns.iter_mut().flatten() {
+                *pattern = absolute_glob(dir, pattern, literal);
+            }
+        }
+
+        let extends = match config.extends.take() {
+            None => return Ok(config),
+            Some(Extends::One(specifier)) => vec![specifier],
+            Some(Extends::Many(specifiers)) => specifiers,
+        };
+
+        seen.push(path.clone());
+        // later entries in `extends` override earlier ones, and the config
+        // itself overrides all of them.
+        let mut base = Self::default();
+        for specifier in extends {
+            let base_path = resolve_extends(dir, &specifier).ok_or_else(|| {
+                Report::msg(format!(
+                    "Could not find tsconfig '{specifier}' extended by {}",
+                    path.display()
+                ))
+            })?;
+            trace!("'{}' extends '{}'", path.display(), base_path.display());
+            base = Self::load_inner(&base_path, seen)?.merge(base);
+        }
+        seen.pop();
+
+        Ok(config.merge(base))
+    }
+
+    /// Add the set of files that should be compiled from `include`, `exclude`
+    /// and `files` to `builder`, using the same defaults as `tsc`.
+    ///
+    /// `include` only lists code, so `assets` globs are included too, or the
+    /// walker would never see assets. They are still excluded by `exclude`.
+    pub fn add_file_overrides(
+        &self,
+        builder: &mut OverrideBuilder,
+        assets: &[String],
+    ) -> Result<()> {
+        // later globs take precedence, so includes come first and explicit
+        // files come last.
+        let include = match (&self.include, &self.files) {
+            (Some(include), _) => include.clone(),
+            (None, Some(_)) => vec![],
+            (None, None) => vec![absolute_glob(&self.dir, "**/*", false)],
+        };
+        let exclude = self.exclude.clone().unwrap_or_else(|| {
+            let mut exclude: Vec<_> = ["node_modules", "bower_components", "jspm_packages"]
+                .into_iter()
+                .map(|dir| absolute_glob(&self.dir, dir, false))
+                .collect();
+            if let Some(out_dir) = self.compiler_options().and_then(|co| co.out_dir.as_ref()) {
+                exclude.push(absolute_glob(out_dir, ".", true));
+            }
+            exclude
+        });
+
+        let include = include.into_iter().map(|glob| {
+            if is_directory_pattern(&glob) {
+                format!("{glob}/**")
+            } else {
+                glob
+            }
+        });
+        // excluding a folder's contents too, rather than just skipping the
+        // folder, lets `files` inside it be whitelisted along with their
+        // parent folders
+        let exclude = exclude
+            .into_iter()
+            .flat_map(|glob| [format!("!{glob}"), format!("!{glob}/**")]);
+        let files = self.files.iter().flatten().flat_map(|glob| {
+            let parents = glob
+                .match_indices('/')
+                .skip(1)
+                .map(|(i, _)| glob[..i].to_string());
+            parents.chain([glob.clone()])
+        });
+        for glob in include
+            .chain(assets.iter().cloned())
+            .chain(exclude)
+            .chain(files)
+        {
+            builder
+                .add(&glob)
+                .into_diagnostic()
+                .context("Invalid include, exclude or files in tsconfig")?;
+        }
+
+        Ok(())
+    }
+
+    fn parse(mut source_text: String) -> Result<Self> {
+        json_strip_comments::strip(&mut source_text).unwrap();
+
+        serde_json::from_str(&source_text).into_diagnostic()
+    }
+
+    /// Merge `base` into this config, with values in `self` taking precedence.
+    fn merge(self, base: Self) -> Self {
+        let compiler_options = match (self.compiler_options, base.compiler_options) {
+            (Some(co), Some(base)) => Some(co.merge(base)),
+            (co, base) => co.or(base),
+        };
+        Self {
+            dir: self.dir,
+            extends: None,
+            compiler_options,
+            include: self.include.or(base.include),
+            exclude: self.exclude.or(base.exclude),
+            files: self.files.or(base.files),
+        }
+    }
+}
+
+impl TsConfigCompilerOptions {
+    fn resolve_paths(&mut self, dir: &Path) {
+        if self.paths.is_some() {
+            self.paths_dir = Some(dir.to_path_buf());
+        }
+        for path in [&mut self.root_dir, &mut self.out_dir, &mut self.base_url]
+            .into_iter()
+            .flatten()
+        {
+            *path = dir.join(&*path);
+        }
+    }
+
+    fn merge(self, base: Self) -> Self {
+        let (paths, paths_dir) = match self.paths {
+            Some(paths) => (Some(paths), self.paths_dir),
+            None => (base.paths, base.paths_dir),
+        };
+        Self {
+            root_dir: self.root_dir.or(base.root_dir),
+            out_dir: self.out_dir.or(base.out_dir),
+            strip_internal: self.strip_internal.or(base.strip_internal),
+            isolated_declarations: self.isolated_declarations.or(base.isolated_declarations),
+            declaration_map: self.declaration_map.or(base.declaration_map),
+            target: self.target.or(base.target),
+            module: self.module.or(base.module),
+            source_map: self.source_map.or(base.source_map),
+            inline_source_map: self.inline_source_map.or(base.inline_source_map),
+            inline_sources: self.inline_sources.or(base.inline_sources),
+            source_root: self.source_root.or(base.source_root),
+            map_root: self.map_root.or(base.map_root),
+            no_emit_on_error: self.no_emit_on_error.or(base.no_emit_on_error),
+            jsx: self.jsx.or(base.jsx),
+            jsx_import_source: self.jsx_import_source.or(base.jsx_import_source),
+            jsx_factory: self.jsx_factory.or(base.jsx_factory),
+            jsx_fragment_factory: self.jsx_fragment_factory.or(base.jsx_fragment_factory),
+            base_url: self.base_url.or(base.base_url),
+            paths,
+            paths_dir,
+        }
+    }
+}
+
+/// Find the file referenced by an `extends` specifier, using the same rules as
+/// `tsc`: relative and absolute paths are resolved against the extending
+/// config's directory, and anything else is looked up in `node_modules`.
+fn resolve_extends(dir: &Path, specifier: &str) -> Option<PathBuf> {
+    let is_path = specifier.starts_with("./")
+        || specifier.starts_with("../")
+        || Path::new(specifier).is_absolute();
+    if is_path {
+        return resolve_json_file(&dir.join(specifier));
+    }
+
+    dir.ancestors()
+        .map(|ancestor| ancestor.join("node_modules").join(specifier))
+        .find_map(|candidate| {
+            resolve_json_file(&candidate).or_else(|| resolve_package_tsconfig(&candidate))
+        })
+}
+
+fn resolve_json_file(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    let mut with_ext = path.as_os_str().to_owned();
+    with_ext.push(".json");
+    let with_ext = PathBuf::from(with_ext);
+    with_ext.is_file().then_some(with_ext)
+}
+
+/// `extends: "@org/tsconfig"` refers to a package, which may point to its
+/// config using a `tsconfig` field in its `package.json`.
+fn resolve_package_tsconfig(package_dir: &Path) -> Option<PathBuf> {
+    #[derive(Deserialize)]
+    struct PackageJson {
+        tsconfig: Option<String>,
+    }
+
+    if !package_dir.is_dir() {
+        return None;
+    }
+    let field = fs::read_to_string(package_dir.join("package.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str::<PackageJson>(&text).ok())
+        .and_then(|package_json| package_json.tsconfig);
+    match field {
+        Some(tsconfig) => resolve_json_file(&package_dir.join(tsconfig)),
+        None => resolve_json_file(&package_dir.join("tsconfig.json")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+    use crate::path::normalize;
+
+    /// A temporary folder containing `files`, as `(path, contents)`.
+    fn project(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn load(root: &Path, path: &str) -> Result<TsConfig> {
+        TsConfig::load(&root.join(path))
+    }
+
+    fn target(config: &TsConfig) -> Option<&str> {
+        config.compiler_options()?.target.as_deref()
+    }
+
+    #[test]
+    fn test_extends_relative_path() {
+        let root = project(&[
+            (
+                "tsconfig.json",
+                r#"{
+                    // comments are allowed
+                    "extends": "./configs/base",
+                    "compilerOptions": { "module": "commonjs" }
+                }"#,
+            ),
+            (
+                "configs/base.json",
+                r#"{ "compilerOptions": { "target": "es2020", "module": "esnext" } }"#,
+            ),
+        ]);
+        let config = load(root.path(), "tsconfig.json").unwrap();
+        let co = config.compiler_options().unwrap();
+        assert_eq!(co.target.as_deref(), Some("es2020"));
+        assert_eq!(co.module.as_deref(), Some("commonjs"));
+    }
+
+    #[test]
+    fn test_extends_package() {
+        let root = project(&[
+            (
+                "packages/app/tsconfig.json",
+                r#"{ "extends": "@org/tsconfig" }"#,
+            ),
+            (
+                "packages/lib/tsconfig.json",
+                r#"{ "extends": "@org/tsconfig/strict.json" }"#,
+            ),
+            ("packages/plain/tsconfig.json", r#"{ "extends": "plain" }"#),
+            (
+                "node_modules/@org/tsconfig/package.json",
+                r#"{ "name": "@org/tsconfig", "tsconfig": "./base.json" }"#,
+            ),
+            (
+                "node_modules/@org/tsconfig/base.json",
+                r#"{ "compilerOptions": { "target": "es2019" } }"#,
+            ),
+            (
+                "node_modules/@org/tsconfig/strict.json",
+                r#"{ "compilerOptions": { "target": "es2022" } }"#,
+            ),
+            (
+                "node_modules/plain/tsconfig.json",
+                r#"{ "compilerOptions": { "target": "es2017" } }"#,
+            ),
+        ]);
+        let target_of = |path| target(&load(root.path(), path).unwrap()).map(str::to_string);
+        // found in an ancestor's `node_modules`, through `package.json`
+        assert_eq!(
+            target_of("packages/app/tsconfig.json").as_deref(),
+            Some("es2019")
+        );
+        assert_eq!(
+            target_of("packages/lib/tsconfig.json").as_deref(),
+            Some("es2022")
+        );
+        assert_eq!(
+            target_of("packages/plain/tsconfig.json").as_deref(),
+            Some("es2017")
+        );
+    }
+
+    #[test]
+    fn test_extends_array() {
+        let root = project(&[
+            (
+                "tsconfig.json",
+                r#"{
+                    "extends": ["./a.json", "./b.json"],
+                    "compilerOptions": { "jsx": "preserve" }
+                }"#,
+            ),
+            (
+                "a.json",
+                r#"{ "compilerOptions": { "target": "es2018", "module": "esnext", "jsx": "react" } }"#,
+            ),
+            (
+                "b.json",
+                r#"{ "compilerOptions": { "target": "es2021", "jsx": "react-jsx" } }"#,
+            ),
+        ]);
+        let config = load(root.path(), "tsconfig.json").unwrap();
+        let co = config.compiler_options().unwrap();
+        // later entries override earlier ones, and the config overrides both
+        assert_eq!(co.target.as_deref(), Some("es2021"));
+        assert_eq!(co.module.as_deref(), Some("esnext"));
+        assert_eq!(co.jsx.as_deref(), Some("preserve"));
+    }
+
+    /// Relative paths are resolved against the file that declares them, not
+    /// the one that extends it.
+    #[test]
+    fn test_extends_resolves_paths_from_declaring_file() {
+        let root = project(&[
+            (
+                "packages/app/tsconfig.json",
+                r#"{ "extends": "../../configs/base.json", "include": ["lib"] }"#,
+            ),
+            (
+                "configs/base.json",
+                r#"{
+                    "compilerOptions": { "rootDir": "./src", "outDir": "../build" },
+                    "exclude": ["./fixtures"]
+                }"#,
+            ),
+        ]);
+        let dir = root.path().canonicalize().unwrap();
+        let config = load(root.path(), "packages/app/tsconfig.json").unwrap();
+        let co = config.compiler_options().unwrap();
+        assert_eq!(
+            co.root_dir.as_deref().map(normalize),
+            Some(dir.join("configs/src"))
+        );
+        assert_eq!(
+            co.out_dir.as_deref().map(normalize),
+            Some(dir.join("build"))
+        );
+        let glob = |path: &Path| absolute_glob(path, ".", true);
+        assert_eq!(
+            config.include,
+            Some(vec![glob(&dir.join("packages/app/lib"))])
+        );
+        assert_eq!(
+            config.exclude,
+            Some(vec![glob(&dir.join("configs/fixtures"))])
+        );
+    }
+
+    #[test]
+    fn test_extends_cycle() {
+        let root = project(&[
+            ("tsconfig.json", r#"{ "extends": "./a.json" }"#),
+            ("a.json", r#"{ "extends": "./b.json" }"#),
+            ("b.json", r#"{ "extends": "./a.json" }"#),
+        ]);
+        let error = load(root.path(), "tsconfig.json").unwrap_err();
+        assert!(error.to_string().contains("Circularity"), "{error}");
+    }
+
+    #[test]
+    fn test_extends_missing() {
+        let root = project(&[("tsconfig.json", r#"{ "extends": "./nope.json" }"#)]);
+        let error = load(root.path(), "tsconfig.json").unwrap_err();
+        assert!(error.to_string().contains("Could not find"), "{error}");
+    }
+
+    fn file_overrides(config: &TsConfig) -> ignore::overrides::Override {
+        let mut builder = OverrideBuilder::new("/");
+        config.add_file_overrides(&mut builder, &[]).unwrap();
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_out_dir_excluded_by_default() {
+        let root = project(&[(
+            "tsconfig.json",
+            r#"{ "compilerOptions": { "outDir": "./build" } }"#,
+        )]);
+        let dir = root.path().canonicalize().unwrap();
+        let overrides = file_overrides(&load(root.path(), "tsconfig.json").unwrap());
+        assert!(overrides.matched(dir.join("build"), true).is_ignore());
+        assert!(overrides.matched(dir.join("build/a.ts"), false).is_ignore());
+        assert!(overrides
+            .matched(dir.join("node_modules"), true)
+            .is_ignore());
+        assert!(overrides
+            .matched(dir.join("src/a.ts"), false)
+            .is_whitelist());
+
+        // an explicit `exclude` replaces the defaults, like in `tsc`
+        fs::write(
+            dir.join("tsconfig.json"),
+            r#"{ "compilerOptions": { "outDir": "./build" }, "exclude": ["tmp"] }"#,
+        )
+        .unwrap();
+        let overrides = file_overrides(&load(root.path(), "tsconfig.json").unwrap());
+        assert!(overrides
+            .matched(dir.join("build/a.ts"), false)
+            .is_whitelist());
+        assert!(overrides.matched(dir.join("tmp/a.ts"), false).is_ignore());
+    }
+}
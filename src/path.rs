@@ -0,0 +1,59 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Remove `.` and `..` from `path` without touching the file system.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Path from the directory `from` to `to` using `/` separators, e.g.
+/// `../lib/foo.ts`. Empty when both are the same. Both paths must be
+/// absolute.
+pub fn relative_url(from: &Path, to: &Path) -> String {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let up = (common..from.len()).map(|_| "..".to_string());
+    let down = to[common..]
+        .iter()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned());
+    up.chain(down).collect::<Vec<_>>().join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let normalize = |path: &str| normalize(Path::new(path));
+        assert_eq!(
+            normalize("/project/./src/lib/../foo.ts"),
+            PathBuf::from("/project/src/foo.ts")
+        );
+        assert_eq!(normalize("/project/src/"), PathBuf::from("/project/src"));
+        assert_eq!(normalize("/project/../../foo"), PathBuf::from("/foo"));
+        assert_eq!(normalize("./src/./foo"), PathBuf::from("src/foo"));
+    }
+
+    #[test]
+    fn test_relative_url() {
+        let url = |from: &str, to: &str| relative_url(Path::new(from), Path::new(to));
+        assert_eq!(url("/project/src", "/project/src/foo.ts"), "foo.ts");
+        assert_eq!(
+            url("/project/dist/lib", "/project/src/foo.ts"),
+            "../../src/foo.ts"
+        );
+        assert_eq!(url("/project/src", "/project/src"), "");
+    }
+}
@@ -1,32 +1,249 @@
-use oxc::diagnostics::DiagnosticService;
+mod github;
+mod info;
+mod json;
+mod junit;
+mod sarif;
 
-// re-export in case we want to modify/wrap/replace/whatever in the future
-pub use oxc::diagnostics::DiagnosticSender;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    io::{self, BufWriter, Stdout, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc,
+};
 
+use miette::{IntoDiagnostic, Result, WrapErr};
+use oxc::diagnostics::{DiagnosticTuple, Error, GraphicalReportHandler};
+
+use info::DiagnosticInfo;
+
+/// What build jobs send to the [`Reporter`].
+#[derive(Debug)]
+pub enum Message {
+    /// Diagnostics about a file, which may be a source file, a config file
+    /// or an output.
+    Diagnostics(DiagnosticTuple),
+    /// A source file was compiled, or was attempted to be. Report files list
+    /// these along with their diagnostics, if any.
+    Compiled(PathBuf),
+    /// The job has finished.
+    Done,
+}
+
+pub type DiagnosticSender = mpsc::Sender<Message>;
+
+/// How diagnostics are printed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Human-readable reports with code frames.
+    #[default]
+    Graphical,
+    /// One JSON object per line, for tools and editor integrations.
+    Json,
+    /// GitHub Actions workflow commands, which show diagnostics as
+    /// annotations on pull requests.
+    Github,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "graphical" => Ok(Self::Graphical),
+            "json" => Ok(Self::Json),
+            "github" => Ok(Self::Github),
+            _ => Err(format!("Unsupported report format '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Graphical => "graphical",
+            Self::Json => "json",
+            Self::Github => "github",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A report written to a file after each build, from `--report kind=path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportFile {
+    /// SARIF 2.1.0, for code scanning dashboards.
+    Sarif(PathBuf),
+    /// JUnit XML, with one test case per compiled file.
+    Junit(PathBuf),
+}
+
+impl FromStr for ReportFile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((kind, path)) = s.split_once('=').filter(|(_, path)| !path.is_empty()) else {
+            return Err(format!(
+                "Invalid report '{s}'. Expected <kind>=<path>, e.g. sarif=oxbuild.sarif"
+            ));
+        };
+        match kind {
+            "sarif" => Ok(Self::Sarif(path.into())),
+            "junit" => Ok(Self::Junit(path.into())),
+            _ => Err(format!(
+                "Unsupported report kind '{kind}'. Expected sarif or junit"
+            )),
+        }
+    }
+}
+
+/// Receives diagnostics from build jobs and prints them to stdout.
 #[must_use]
 pub struct Reporter {
-    inner: DiagnosticService,
+    format: ReportFormat,
+    handler: GraphicalReportHandler,
+    writer: BufWriter<Stdout>,
+    receiver: mpsc::Receiver<Message>,
+    errors_count: usize,
+    warnings_count: usize,
+    report_files: Vec<ReportFile>,
+    /// Diagnostics for each file seen since report files were last written.
+    /// Only kept when there are report files.
+    files: BTreeMap<PathBuf, Vec<DiagnosticInfo>>,
+    /// Source files compiled since report files were last written. Only kept
+    /// when there are report files.
+    compiled: BTreeSet<PathBuf>,
 }
 
 impl Reporter {
-    pub fn new() -> (Self, DiagnosticSender) {
-        trace!("Creating diagnostics reporter");
-        let inner = DiagnosticService::default();
-        let sender = inner.sender().clone();
-        (Self { inner }, sender)
+    pub fn new(format: ReportFormat, report_files: Vec<ReportFile>) -> (Self, DiagnosticSender) {
+        trace!("Creating {format} diagnostics reporter");
+        let (sender, receiver) = mpsc::channel();
+        let reporter = Self {
+            format,
+            handler: GraphicalReportHandler::new(),
+            writer: BufWriter::new(io::stdout()),
+            receiver,
+            errors_count: 0,
+            warnings_count: 0,
+            report_files,
+            files: BTreeMap::new(),
+            compiled: BTreeSet::new(),
+        };
+        (reporter, sender)
     }
 
+    /// Print diagnostics as they are received, until [`Message::Done`] is
+    /// sent.
+    ///
+    /// # Panics
+    /// If writing to stdout fails.
     pub fn run(&mut self) {
-        self.inner.run();
+        loop {
+            let (path, diagnostics) = match self.receiver.recv() {
+                Ok(Message::Diagnostics(diagnostics)) => diagnostics,
+                Ok(Message::Compiled(path)) => {
+                    if !self.report_files.is_empty() {
+                        self.compiled.insert(path);
+                    }
+                    continue;
+                }
+                Ok(Message::Done) | Err(_) => break,
+            };
+            let mut infos = Vec::with_capacity(diagnostics.len());
+            for diagnostic in diagnostics {
+                let info = DiagnosticInfo::new(&path, &diagnostic);
+                match info.severity {
+                    "error" => self.errors_count += 1,
+                    "warning" => self.warnings_count += 1,
+                    _ => {}
+                }
+                let rendered = self.render(&diagnostic, &info);
+                write_stdout(&mut self.writer, rendered.as_bytes());
+                infos.push(info);
+            }
+            if !self.report_files.is_empty() {
+                self.files.entry(path).or_default().extend(infos);
+            }
+        }
+        flush_stdout(&mut self.writer);
+    }
+
+    fn render(&self, diagnostic: &Error, info: &DiagnosticInfo) -> String {
+        match self.format {
+            ReportFormat::Graphical => {
+                let mut output = String::new();
+                self.handler
+                    .render_report(&mut output, diagnostic.as_ref())
+                    .unwrap();
+                output
+            }
+            ReportFormat::Json => json::render(info),
+            ReportFormat::Github => github::render(info),
+        }
+    }
+
+    /// Write report files for everything reported since they were last
+    /// written.
+    pub fn write_reports(&mut self) -> Result<()> {
+        let files = std::mem::take(&mut self.files);
+        let compiled = std::mem::take(&mut self.compiled);
+        for report_file in &self.report_files {
+            let (path, contents) = match report_file {
+                ReportFile::Sarif(path) => (path, sarif::render(&files)),
+                ReportFile::Junit(path) => (path, junit::render(&compiled, &files)),
+            };
+            debug!("Writing report to '{}'", path.display());
+            fs::write(path, contents)
+                .into_diagnostic()
+                .with_context(|| format!("Failed to write report to {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Print a message that isn't a diagnostic, like a build summary.
+    ///
+    /// Machine-readable formats print these to stderr, so stdout only
+    /// contains diagnostics.
+    #[allow(clippy::print_stdout, clippy::print_stderr)]
+    pub fn print(&self, message: &str) {
+        match self.format {
+            ReportFormat::Graphical => println!("{message}"),
+            ReportFormat::Json => eprintln!("{message}"),
+            // anything that isn't a workflow command is plain log output
+            ReportFormat::Github => println!("{message}"),
+        }
     }
 
     #[inline]
     pub fn errors_count(&self) -> usize {
-        self.inner.errors_count()
+        self.errors_count
     }
 
     #[inline]
     pub fn warnings_count(&self) -> usize {
-        self.inner.warnings_count()
+        self.warnings_count
+    }
+}
+
+/// Write to stdout, ignoring errors from closed pipes (e.g. piping into
+/// `head`).
+fn write_stdout(writer: &mut BufWriter<Stdout>, bytes: &[u8]) {
+    ignore_closed_pipe(writer.write_all(bytes));
+}
+
+fn flush_stdout(writer: &mut BufWriter<Stdout>) {
+    ignore_closed_pipe(writer.flush());
+}
+
+fn ignore_closed_pipe(result: io::Result<()>) {
+    match result {
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::BrokenPipe | io::ErrorKind::Interrupted
+            ) => {}
+        result => result.unwrap(),
     }
 }
@@ -0,0 +1,59 @@
+use std::env;
+
+use super::info::DiagnosticInfo;
+
+/// Render a diagnostic as a GitHub Actions workflow command, so it shows up
+/// as an annotation on the changed lines of a pull request.
+///
+/// See <https://docs.github.com/en/actions/writing-workflows/choosing-what-your-workflow-does/workflow-commands-for-github-actions#setting-an-error-message>
+pub(super) fn render(info: &DiagnosticInfo) -> String {
+    let command = match info.severity {
+        "error" => "error",
+        "warning" => "warning",
+        _ => "notice",
+    };
+    // annotations only appear on the diff when paths are relative to the
+    // repository root
+    let workspace = env::var_os("GITHUB_WORKSPACE");
+    let file = workspace
+        .as_ref()
+        .and_then(|workspace| info.file.strip_prefix(workspace).ok())
+        .unwrap_or(&info.file);
+
+    let mut properties = vec![format!("file={}", escape_property(&file.to_string_lossy()))];
+    if let Some(label) = info.labels.first() {
+        properties.push(format!("line={}", label.line));
+        properties.push(format!("endLine={}", label.end_line));
+        // columns are only allowed for annotations on a single line
+        if label.line == label.end_line {
+            properties.push(format!("col={}", label.column));
+            properties.push(format!("endColumn={}", label.end_column));
+        }
+    }
+    let title = info
+        .code
+        .as_ref()
+        .map_or_else(|| "oxbuild".to_string(), |code| format!("oxbuild {code}"));
+    properties.push(format!("title={}", escape_property(&title)));
+    let message = match &info.help {
+        Some(help) => format!("{}\nhelp: {help}", info.message),
+        None => info.message.clone(),
+    };
+
+    format!(
+        "::{command} {}::{}\n",
+        properties.join(","),
+        escape_data(&message)
+    )
+}
+
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+
+use miette::{SourceCode, SourceSpan};
+use oxc::diagnostics::{Error, Severity};
+use serde::Serialize;
+
+/// What reporters need to know about a diagnostic, in a form that can be
+/// kept after the diagnostic has been printed. Serialized as-is by
+/// `--format json`.
+#[derive(Debug, Serialize)]
+pub(super) struct DiagnosticInfo {
+    pub file: PathBuf,
+    /// `"error"`, `"warning"` or `"advice"`.
+    pub severity: &'static str,
+    /// e.g. `TS9007`.
+    pub code: Option<String>,
+    pub message: String,
+    pub labels: Vec<LabelInfo>,
+    pub help: Option<String>,
+}
+
+/// A labeled span of source code. Offsets are in bytes; lines and columns
+/// start at 1 and columns count bytes.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct LabelInfo {
+    pub label: Option<String>,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    /// `column` counted in UTF-16 code units, for SARIF.
+    #[serde(skip)]
+    pub utf16_column: usize,
+    #[serde(skip)]
+    pub utf16_end_column: usize,
+}
+
+impl DiagnosticInfo {
+    pub fn new(path: &Path, diagnostic: &Error) -> Self {
+        let labels = match (diagnostic.labels(), diagnostic.source_code()) {
+            (Some(labels), Some(source)) => labels
+                .map(|label| {
+                    let start = label.offset();
+                    let end = start + label.len();
+                    let (line, column) = line_column(source, start);
+                    let (end_line, end_column) = line_column(source, end);
+                    LabelInfo {
+                        label: label.label().map(ToString::to_string),
+                        start,
+                        end,
+                        line,
+                        column,
+                        end_line,
+                        end_column,
+                        utf16_column: utf16_column(source, start),
+                        utf16_end_column: utf16_column(source, end),
+                    }
+                })
+                .collect(),
+            _ => vec![],
+        };
+        let (code, message) = split_code(diagnostic);
+        Self {
+            file: path.to_path_buf(),
+            severity: match diagnostic.severity() {
+                Some(Severity::Error) | None => "error",
+                Some(Severity::Warning) => "warning",
+                Some(Severity::Advice) => "advice",
+            },
+            code,
+            message,
+            labels,
+            help: diagnostic.help().map(|help| help.to_string()),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == "error"
+    }
+}
+
+/// Split a diagnostic's message into its code and the rest of the message.
+///
+/// Codes are part of the message text, e.g. `TS9007: Function must have...`
+/// or `eslint(no-debugger): ...`. Other prefixes, like in `Unexpected token:
+/// ...`, are left in the message.
+fn split_code(diagnostic: &Error) -> (Option<String>, String) {
+    let message = diagnostic.to_string();
+    match message.split_once(": ") {
+        Some((code, rest)) if is_code(code) => (Some(code.to_string()), rest.to_string()),
+        _ => (diagnostic.code().map(|code| code.to_string()), message),
+    }
+}
+
+/// Is `text` shaped like a diagnostic code: `TS` and a number, or a scope and
+/// a rule name like `eslint(no-debugger)`?
+fn is_code(text: &str) -> bool {
+    if let Some(number) = text.strip_prefix("TS") {
+        return !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit());
+    }
+    let is_name = |name: &str| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '/' | '@'))
+    };
+    text.strip_suffix(')')
+        .and_then(|text| text.split_once('('))
+        .is_some_and(|(scope, rule)| is_name(scope) && is_name(rule))
+}
+
+/// 1-based line and column of `offset` in `source`.
+fn line_column(source: &dyn SourceCode, offset: usize) -> (usize, usize) {
+    source
+        .read_span(&SourceSpan::new(offset.into(), 0), 0, 0)
+        .map_or((1, 1), |contents| {
+            (contents.line() + 1, contents.column() + 1)
+        })
+}
+
+/// 1-based column of `offset` in `source`, counted in UTF-16 code units.
+fn utf16_column(source: &dyn SourceCode, offset: usize) -> usize {
+    let Ok(contents) = source.read_span(&SourceSpan::new(0.into(), offset), 0, 0) else {
+        return 1;
+    };
+    let before = contents.data();
+    let line_start = before
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |i| i + 1);
+    String::from_utf8_lossy(&before[line_start..])
+        .encode_utf16()
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+impl DiagnosticInfo {
+    /// Info for `diagnostic`, reported for `path` with `source_text` as its
+    /// contents, like the walker does.
+    pub fn for_test(
+        path: &str,
+        source_text: &str,
+        diagnostic: oxc::diagnostics::OxcDiagnostic,
+    ) -> Self {
+        let source = miette::NamedSource::new(path, source_text.to_string());
+        let error = Error::new(diagnostic).with_source_code(source);
+        Self::new(Path::new(path), &error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use oxc::diagnostics::OxcDiagnostic;
+
+    use super::*;
+
+    fn split(message: &'static str) -> (Option<String>, String) {
+        let info = DiagnosticInfo::for_test("index.ts", "", OxcDiagnostic::error(message));
+        (info.code, info.message)
+    }
+
+    #[test]
+    fn test_split_code() {
+        assert_eq!(
+            split("TS9007: Function must have an explicit return type"),
+            (
+                Some("TS9007".to_string()),
+                "Function must have an explicit return type".to_string()
+            )
+        );
+        assert_eq!(
+            split("eslint(no-debugger): `debugger` statement is not allowed"),
+            (
+                Some("eslint(no-debugger)".to_string()),
+                "`debugger` statement is not allowed".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_split_code_ignores_other_prefixes() {
+        for message in [
+            "Unexpected token: `)`",
+            "foo.ts: not found",
+            "TS: missing number",
+            "TSX9007: not a code",
+            "Failed to write '/dist/a.js': Permission denied",
+            "(rule): no scope",
+        ] {
+            assert_eq!(split(message), (None, message.to_string()));
+        }
+    }
+}
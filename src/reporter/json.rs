@@ -0,0 +1,8 @@
+use super::info::DiagnosticInfo;
+
+/// Render a diagnostic as a single line of JSON, including the newline.
+pub(super) fn render(info: &DiagnosticInfo) -> String {
+    let mut line = serde_json::to_string(info).unwrap();
+    line.push('\n');
+    line
+}
@@ -0,0 +1,214 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use super::info::DiagnosticInfo;
+
+/// Render a JUnit XML report with one test case per `compiled` file. Files
+/// with errors fail; warnings are included as test output.
+///
+/// Diagnostics for other files, like config warnings or failed writes to
+/// `dist`, aren't test cases. Paths are relative to the current directory
+/// when possible.
+pub(super) fn render(
+    compiled: &BTreeSet<PathBuf>,
+    files: &BTreeMap<PathBuf, Vec<DiagnosticInfo>>,
+) -> String {
+    render_in(compiled, files, env::current_dir().ok().as_deref())
+}
+
+fn render_in(
+    compiled: &BTreeSet<PathBuf>,
+    files: &BTreeMap<PathBuf, Vec<DiagnosticInfo>>,
+    cwd: Option<&Path>,
+) -> String {
+    let files: Vec<_> = compiled
+        .iter()
+        .map(|path| (path, files.get(path).map_or(&[][..], Vec::as_slice)))
+        .collect();
+    let relative = |path: &Path| -> String {
+        cwd.and_then(|cwd| path.strip_prefix(cwd).ok())
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    };
+    let failures = files
+        .iter()
+        .filter(|(_, diagnostics)| diagnostics.iter().any(DiagnosticInfo::is_error))
+        .count();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"oxbuild\" tests=\"{}\" failures=\"{failures}\">",
+        files.len()
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"oxbuild\" tests=\"{}\" failures=\"{failures}\" errors=\"0\">",
+        files.len()
+    );
+    for (path, diagnostics) in files {
+        let name = escape(&relative(path));
+        let _ = write!(
+            xml,
+            "    <testcase name=\"{name}\" classname=\"oxbuild\" file=\"{name}\""
+        );
+        if diagnostics.is_empty() {
+            xml.push_str(" />\n");
+            continue;
+        }
+        xml.push_str(">\n");
+
+        let (errors, warnings): (Vec<_>, Vec<_>) =
+            diagnostics.iter().partition(|info| info.is_error());
+        if let Some(first) = errors.first() {
+            let _ = writeln!(
+                xml,
+                "      <failure message=\"{}\" type=\"error\">{}</failure>",
+                escape(&first.message),
+                escape(&describe(&relative(path), &errors))
+            );
+        }
+        if !warnings.is_empty() {
+            let _ = writeln!(
+                xml,
+                "      <system-out>{}</system-out>",
+                escape(&describe(&relative(path), &warnings))
+            );
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// One line per diagnostic, like `src/index.ts:1:17: error TS9007: message`.
+fn describe(file: &str, diagnostics: &[&DiagnosticInfo]) -> String {
+    let mut text = String::new();
+    for info in diagnostics {
+        text.push_str(file);
+        if let Some(label) = info.labels.first() {
+            let _ = write!(text, ":{}:{}", label.line, label.column);
+        }
+        let _ = write!(text, ": {}", info.severity);
+        if let Some(code) = &info.code {
+            let _ = write!(text, " {code}");
+        }
+        let _ = writeln!(text, ": {}", info.message);
+        if let Some(help) = &info.help {
+            let _ = writeln!(text, "  help: {help}");
+        }
+    }
+    text
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        path::{Path, PathBuf},
+    };
+
+    use oxc::{diagnostics::OxcDiagnostic, span::Span};
+
+    use super::{render_in, DiagnosticInfo};
+
+    #[test]
+    fn test_render() {
+        let source = "export function f(a) {}\nlet x = <b>;";
+        let compiled: BTreeSet<_> = [
+            "/project/src/a.ts",
+            "/project/src/b.ts",
+            "/project/src/c.ts",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+        let mut files = BTreeMap::new();
+        files.insert(
+            PathBuf::from("/project/src/a.ts"),
+            vec![
+                DiagnosticInfo::for_test(
+                    "/project/src/a.ts",
+                    source,
+                    OxcDiagnostic::error("TS9007: Function must have an explicit return type")
+                        .with_label(Span::new(16, 17))
+                        .with_help("Add a return type"),
+                ),
+                DiagnosticInfo::for_test(
+                    "/project/src/a.ts",
+                    source,
+                    OxcDiagnostic::warn("Unexpected \"<b>\"").with_label(Span::new(32, 35)),
+                ),
+            ],
+        );
+        files.insert(
+            PathBuf::from("/project/src/b.ts"),
+            vec![DiagnosticInfo::for_test(
+                "/project/src/b.ts",
+                "",
+                OxcDiagnostic::warn("Something & something"),
+            )],
+        );
+        // neither of these are compiled source files
+        files.insert(
+            PathBuf::from("/project/dist/c.js"),
+            vec![DiagnosticInfo::for_test(
+                "/project/dist/c.js",
+                "",
+                OxcDiagnostic::error("Failed to write '/project/dist/c.js'"),
+            )],
+        );
+        files.insert(
+            PathBuf::from("/project/oxbuild.json"),
+            vec![DiagnosticInfo::for_test(
+                "/project/oxbuild.json",
+                "",
+                OxcDiagnostic::warn("Unknown option 'typo'"),
+            )],
+        );
+
+        assert_eq!(
+            render_in(&compiled, &files, Some(Path::new("/project"))),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="oxbuild" tests="3" failures="1">
+  <testsuite name="oxbuild" tests="3" failures="1" errors="0">
+    <testcase name="src/a.ts" classname="oxbuild" file="src/a.ts">
+      <failure message="Function must have an explicit return type" type="error">src/a.ts:1:17: error TS9007: Function must have an explicit return type
+  help: Add a return type
+</failure>
+      <system-out>src/a.ts:2:9: warning: Unexpected &quot;&lt;b&gt;&quot;
+</system-out>
+    </testcase>
+    <testcase name="src/b.ts" classname="oxbuild" file="src/b.ts">
+      <system-out>src/b.ts: warning: Something &amp; something
+</system-out>
+    </testcase>
+    <testcase name="src/c.ts" classname="oxbuild" file="src/c.ts" />
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
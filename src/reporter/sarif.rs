@@ -0,0 +1,228 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use super::info::DiagnosticInfo;
+
+/// Render diagnostics from a build as a SARIF 2.1.0 log, for code scanning
+/// tools.
+///
+/// Paths are relative to the current directory, which is given as the
+/// `SRCROOT` base URI.
+pub(super) fn render(files: &BTreeMap<PathBuf, Vec<DiagnosticInfo>>) -> String {
+    render_in(files, env::current_dir().ok().as_deref())
+}
+
+fn render_in(files: &BTreeMap<PathBuf, Vec<DiagnosticInfo>>, cwd: Option<&Path>) -> String {
+    let results: Vec<Value> = files
+        .values()
+        .flatten()
+        .map(|info| result(info, cwd))
+        .collect();
+    let mut rules: Vec<&str> = files
+        .values()
+        .flatten()
+        .filter_map(|info| info.code.as_deref())
+        .collect();
+    rules.sort_unstable();
+    rules.dedup();
+
+    let mut run = json!({
+        "tool": {
+            "driver": {
+                "name": "oxbuild",
+                "version": env!("CARGO_PKG_VERSION"),
+                "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                "rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+            }
+        },
+        "results": results,
+        "columnKind": "utf16CodeUnits",
+    });
+    if let Some(cwd) = cwd {
+        run["originalUriBaseIds"] = json!({
+            "SRCROOT": { "uri": format!("{}/", file_uri(cwd)) }
+        });
+    }
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [run],
+    });
+    serde_json::to_string_pretty(&log).unwrap()
+}
+
+fn result(info: &DiagnosticInfo, cwd: Option<&Path>) -> Value {
+    let artifact = match cwd.and_then(|cwd| info.file.strip_prefix(cwd).ok()) {
+        Some(relative) => json!({ "uri": encode_path(relative), "uriBaseId": "SRCROOT" }),
+        None => json!({ "uri": file_uri(&info.file) }),
+    };
+    let mut location = json!({ "physicalLocation": { "artifactLocation": artifact } });
+    if let Some(label) = info.labels.first() {
+        location["physicalLocation"]["region"] = json!({
+            "startLine": label.line,
+            "startColumn": label.utf16_column,
+            "endLine": label.end_line,
+            "endColumn": label.utf16_end_column,
+            "byteOffset": label.start,
+            "byteLength": label.end - label.start,
+        });
+    }
+    let text = match &info.help {
+        Some(help) => format!("{}\nhelp: {help}", info.message),
+        None => info.message.clone(),
+    };
+
+    let mut result = json!({
+        "level": match info.severity {
+            "error" => "error",
+            "warning" => "warning",
+            _ => "note",
+        },
+        "message": { "text": text },
+        "locations": [location],
+    });
+    if let Some(code) = &info.code {
+        result["ruleId"] = json!(code);
+    }
+    result
+}
+
+fn file_uri(path: &Path) -> String {
+    let path = encode_path(path);
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else {
+        // windows paths, like C:/foo
+        format!("file:///{path}")
+    }
+}
+
+/// Percent-encode a path for use in a URI, with `/` separators.
+fn encode_path(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                encoded.push(byte as char);
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    };
+
+    use oxc::{diagnostics::OxcDiagnostic, span::Span};
+    use serde_json::{json, Value};
+
+    use super::{render_in, DiagnosticInfo};
+
+    #[test]
+    fn test_render() {
+        // "é" is two bytes but one UTF-16 code unit
+        let source = "const é = 1;\nfunction f(a) {}";
+        let mut files = BTreeMap::new();
+        files.insert(
+            PathBuf::from("/project/src/my file.ts"),
+            vec![DiagnosticInfo::for_test(
+                "/project/src/my file.ts",
+                source,
+                OxcDiagnostic::error("TS9007: Function must have an explicit return type")
+                    .with_label(Span::new(14, 27))
+                    .with_help("Add a return type"),
+            )],
+        );
+        files.insert(
+            PathBuf::from("/project/src/a.ts"),
+            vec![DiagnosticInfo::for_test(
+                "/project/src/a.ts",
+                source,
+                OxcDiagnostic::warn("Unused").with_label(Span::new(9, 10)),
+            )],
+        );
+        files.insert(
+            PathBuf::from("/elsewhere/b.ts"),
+            vec![DiagnosticInfo::for_test(
+                "/elsewhere/b.ts",
+                "",
+                OxcDiagnostic::error("Failed to read '/elsewhere/b.ts'"),
+            )],
+        );
+
+        let log: Value =
+            serde_json::from_str(&render_in(&files, Some(Path::new("/project")))).unwrap();
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "oxbuild");
+        assert_eq!(run["tool"]["driver"]["rules"], json!([{ "id": "TS9007" }]));
+        assert_eq!(run["columnKind"], "utf16CodeUnits");
+        assert_eq!(
+            run["originalUriBaseIds"],
+            json!({ "SRCROOT": { "uri": "file:///project/" } })
+        );
+        assert_eq!(
+            run["results"],
+            json!([
+                {
+                    "level": "error",
+                    "message": { "text": "Failed to read '/elsewhere/b.ts'" },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": "file:///elsewhere/b.ts" }
+                        }
+                    }],
+                },
+                {
+                    "level": "warning",
+                    "message": { "text": "Unused" },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": "src/a.ts", "uriBaseId": "SRCROOT" },
+                            "region": {
+                                "startLine": 1,
+                                "startColumn": 9,
+                                "endLine": 1,
+                                "endColumn": 10,
+                                "byteOffset": 9,
+                                "byteLength": 1,
+                            }
+                        }
+                    }],
+                },
+                {
+                    "level": "error",
+                    "ruleId": "TS9007",
+                    "message": {
+                        "text": "Function must have an explicit return type\nhelp: Add a return type"
+                    },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": "src/my%20file.ts", "uriBaseId": "SRCROOT" },
+                            "region": {
+                                "startLine": 2,
+                                "startColumn": 1,
+                                "endLine": 2,
+                                "endColumn": 14,
+                                "byteOffset": 14,
+                                "byteLength": 13,
+                            }
+                        }
+                    }],
+                },
+            ])
+        );
+    }
+}
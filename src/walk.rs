@@ -1,55 +1,195 @@
 use std::{
-    fs,
+    fs, io,
     ops::Deref,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
+mod cache;
+mod output;
+mod source_map;
+
 use ignore::{DirEntry, Error as WalkError, ParallelVisitor, ParallelVisitorBuilder, WalkState};
 use oxc::diagnostics::{Error, NamedSource, OxcDiagnostic};
 
 use crate::{
-    compiler::{compile, CompileOptions, CompiledOutput},
+    compiler::{
+        compile, resolutions_match, rewrite_declaration_file, CompileOptions, CompiledCode,
+        CompiledOutput, OutputOptions,
+    },
+    options::{CleanMode, OutputVariant, SourceMapOptions},
+    reporter::Message,
     DiagnosticSender, OxbuildOptions,
 };
+use cache::Cache;
+use output::{Change, Output};
+use source_map::{finish_source_map, FinishedSourceMap};
 
 pub struct WalkerBuilder {
     options: Arc<OxbuildOptions>,
     compile_options: Arc<CompileOptions>,
     sender: DiagnosticSender,
+    output: Output,
+    cache: Option<Arc<Cache>>,
+    /// Have staged outputs been thrown away since the last successful build?
+    /// If so, `dist` is missing files that haven't changed since.
+    discarded: bool,
 }
 
 impl WalkerBuilder {
     pub fn new(options: OxbuildOptions, sender: DiagnosticSender) -> Self {
         let compile_options = CompileOptions::new(options.root.deref().to_path_buf())
-            .with_d_ts(options.isolated_declarations.clone());
+            .with_src(options.src.clone())
+            .with_module_package(options.module_package)
+            .with_preserve_jsx(options.preserve_jsx)
+            .with_d_ts(options.isolated_declarations.clone())
+            .with_source_maps(options.source_maps.is_some())
+            .with_jsx(options.jsx.clone())
+            .with_defines(options.define_config.clone())
+            .with_minify(options.minify)
+            .with_strip_comments(options.strip_comments)
+            .with_paths(options.paths.clone())
+            .with_outputs(
+                options
+                    .variants
+                    .iter()
+                    .map(|variant| OutputOptions {
+                        target: variant.target,
+                        module_format: variant.module_format,
+                    })
+                    .collect(),
+            );
+        let cache = options
+            .cache_dir
+            .clone()
+            .map(|dir| Arc::new(Cache::new(dir, &options)));
         Self {
             compile_options: Arc::new(compile_options),
+            output: Output::new(options.no_emit_on_error),
+            cache,
             options: Arc::new(options),
             sender,
+            discarded: false,
         }
     }
 
     pub fn walk(&mut self, nthreads: usize) {
         debug!("Starting walker with {} threads", nthreads);
-        let inner = ignore::WalkBuilder::new(&self.options.src)
-            // TODO: use ignore to respect tsconfig include/exclude
-            .ignore(false)
+        let inner = self
+            .walk_builder(&self.options.src)
             .threads(nthreads)
-            .hidden(false)
             .build_parallel();
 
+        if let Some(mode) = self.options.clean {
+            self.walker().clean(mode);
+        }
+        if let Some(cache) = &self.cache {
+            cache.reset();
+        }
         inner.visit(self);
+        if let Some(cache) = &self.cache {
+            cache.prune();
+        }
     }
-}
 
-impl<'s> ParallelVisitorBuilder<'s> for WalkerBuilder {
-    fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
-        Box::new(Walker {
+    /// Recompile a set of changed paths under `src`.
+    ///
+    /// Paths that no longer exist are treated as deletions and have their
+    /// outputs removed from `dist`.
+    pub fn rebuild<I>(&mut self, changed: I)
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        let mut walker = self.walker();
+        for path in changed {
+            if !path.starts_with(&walker.options.src) {
+                continue;
+            }
+            if walker.is_excluded(&path) {
+                continue;
+            }
+            if path.is_dir() {
+                // may have been created or moved into `src` with files in it,
+                // which won't get events of their own
+                for entry in self.walk_builder(&path).build() {
+                    walker.visit(entry);
+                }
+            } else if path.exists() {
+                if Walker::is_allowed_ext(&path) || walker.is_asset(&path) {
+                    walker.visit_file(&path);
+                }
+            } else {
+                walker.remove_outputs_for(&path);
+                if let Some(cache) = &walker.cache {
+                    cache.remove(&path);
+                }
+            }
+        }
+    }
+
+    /// Did a previous build throw away its outputs? Rebuilding only changed
+    /// files won't fill in `dist`, so the next build should walk everything.
+    pub fn needs_full_build(&self) -> bool {
+        self.discarded
+    }
+
+    /// Write outputs staged by `noEmitOnError` to `dist`, or throw them away
+    /// if the build had errors. Returns `false` if outputs were thrown away.
+    ///
+    /// Each `dist` folder is replaced as a whole, so if writing fails it is
+    /// left as it was before the build.
+    ///
+    /// Does nothing when outputs are written as they are compiled.
+    pub fn finish(&mut self, had_errors: bool) -> bool {
+        let changes = self.output.take_staged();
+        if had_errors {
+            debug!("Discarding {} staged changes", changes.len());
+            self.discarded |= !changes.is_empty();
+            return changes.is_empty();
+        }
+        self.discarded = false;
+        if changes.is_empty() {
+            return true;
+        }
+        let dists: Vec<_> = self
+            .options
+            .variants
+            .iter()
+            .map(|variant| variant.dist.clone())
+            .collect();
+        if let Err(e) = Output::commit(changes, &dists) {
+            // nothing was written, so the next build must write everything
+            self.discarded = true;
+            self.walker().report_io_error(&e.path, e.action, &e.error);
+        }
+        true
+    }
+
+    /// Walks source files in `dir`, skipping the same files a full build
+    /// does.
+    fn walk_builder(&self, dir: &Path) -> ignore::WalkBuilder {
+        let mut builder = ignore::WalkBuilder::new(dir);
+        builder
+            .overrides(self.options.overrides.clone())
+            .ignore(false)
+            .hidden(false);
+        builder
+    }
+
+    fn walker(&self) -> Walker {
+        Walker {
             options: Arc::clone(&self.options),
             compile_options: Arc::clone(&self.compile_options),
             sender: self.sender.clone(),
-        })
+            output: self.output.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<'s> ParallelVisitorBuilder<'s> for WalkerBuilder {
+    fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
+        Box::new(self.walker())
     }
 }
 
@@ -57,110 +197,768 @@ pub struct Walker {
     options: Arc<OxbuildOptions>,
     compile_options: Arc<CompileOptions>,
     sender: DiagnosticSender,
+    output: Output,
+    cache: Option<Arc<Cache>>,
 }
 
 impl Walker {
     const ALLOWED_EXTS: [&'static str; 8] = ["ts", "tsx", "cts", "mts", "js", "jsx", "mjs", "cjs"];
 
+    /// Is `path` a source file oxbuild compiles, rather than an asset or
+    /// config file?
     fn is_allowed_ext<P: AsRef<Path>>(path: P) -> bool {
         path.as_ref()
             .extension()
             .is_some_and(|ext| Self::ALLOWED_EXTS.iter().any(|&e| e == ext))
     }
 
-    #[must_use]
-    fn compile(&self, path: &Path) -> Option<CompiledOutput> {
-        trace!("Compiling '{}'", path.display());
-        let source_text = match fs::read_to_string(path) {
-            Ok(text) => text,
+    /// Should `path` be copied into `dist` instead of compiled?
+    fn is_asset(&self, path: &Path) -> bool {
+        !Self::is_allowed_ext(path) && self.options.assets.matched(path, false).is_whitelist()
+    }
+
+    /// Is `path` a hand-written declaration file, like `types.d.ts`?
+    fn is_declaration_file(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                [".d.ts", ".d.mts", ".d.cts"]
+                    .iter()
+                    .any(|ext| name.ends_with(ext))
+            })
+    }
+
+    /// Is `path` excluded from compilation by tsconfig `include`, `exclude`
+    /// or `files`?
+    fn is_excluded(&self, path: &Path) -> bool {
+        let overrides = &self.options.overrides;
+        if overrides.matched(path, path.is_dir()).is_ignore() {
+            return true;
+        }
+        // the walker skips excluded directories entirely, so their contents are excluded too
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.options.src))
+            .any(|dir| overrides.matched(dir, true).is_ignore())
+    }
+
+    fn read_source(&self, path: &Path) -> Option<String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Some(text),
             Err(e) => {
-                let error = OxcDiagnostic::error(format!(
-                    "Failed to open source file at '{}': {}",
-                    path.display(),
-                    e
-                ));
-                self.sender
-                    .send(Some((path.to_path_buf(), vec![Error::new(error)])))
-                    .unwrap();
-                return None;
+                self.report_io_error(path, "open source file", &e);
+                None
             }
-        };
+        }
+    }
 
+    /// Compile a file and report its diagnostics. Reported diagnostics are
+    /// left in the output, so callers can tell whether there were warnings.
+    #[must_use]
+    fn compile(&self, path: &Path, source_text: String) -> Option<CompiledOutput> {
+        trace!("Compiling '{}'", path.display());
         match compile(&self.compile_options, path, &source_text) {
-            Ok(output) => Some(output),
+            Ok(output) => {
+                if !output.diagnostics.is_empty() {
+                    self.report(path, source_text, output.diagnostics.clone());
+                }
+                Some(output)
+            }
             Err(diagnostics) => {
-                let source = Arc::new(NamedSource::new(path.to_string_lossy(), source_text));
-                let errors = diagnostics
-                    .into_iter()
-                    .map(|diagnostic| diagnostic.with_source_code(Arc::clone(&source)))
-                    .collect();
-                self.sender
-                    .send(Some((path.to_path_buf(), errors)))
-                    .unwrap();
+                self.report(path, source_text, diagnostics);
                 None
             }
         }
     }
 
-    fn get_output_path_for(&self, dir: &Path) -> PathBuf {
-        let rel = dir.strip_prefix(&self.options.src).unwrap();
-        self.options.dist.join(rel)
+    fn report(&self, path: &Path, source_text: String, diagnostics: Vec<OxcDiagnostic>) {
+        let source = Arc::new(NamedSource::new(path.to_string_lossy(), source_text));
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.with_source_code(Arc::clone(&source)))
+            .collect();
+        self.sender
+            .send(Message::Diagnostics((path.to_path_buf(), diagnostics)))
+            .unwrap();
+    }
+
+    /// Tell the reporter `path` is a source file being compiled, so report
+    /// files list it even if it has no diagnostics.
+    fn report_compiled(&self, path: &Path) {
+        self.sender
+            .send(Message::Compiled(path.to_path_buf()))
+            .unwrap();
+    }
+
+    /// Report a failed file system operation on `path`, e.g.
+    /// `report_io_error(path, "write", &e)`.
+    fn report_io_error(&self, path: &Path, action: &str, error: &io::Error) {
+        let error =
+            OxcDiagnostic::error(format!("Failed to {action} '{}': {error}", path.display()));
+        self.sender
+            .send(Message::Diagnostics((
+                path.to_path_buf(),
+                vec![Error::new(error)],
+            )))
+            .unwrap();
+    }
+
+    fn report_walk_error(&self, error: &WalkError) {
+        let path = walk_error_path(error).unwrap_or(&self.options.src);
+        let error = OxcDiagnostic::error(format!("Failed to read source files: {error}"));
+        self.sender
+            .send(Message::Diagnostics((
+                path.to_path_buf(),
+                vec![Error::new(error)],
+            )))
+            .unwrap();
+    }
+
+    /// Make a change to `dist`, reporting it if it fails.
+    fn apply(&self, change: Change) {
+        if let Err((change, e)) = self.output.apply(change) {
+            self.report_io_error(change.path(), change.action(), &e);
+        }
+    }
+
+    fn write(&self, path: &Path, contents: impl Into<Vec<u8>>) {
+        self.apply(Change::Write(path.to_path_buf(), contents.into()));
+    }
+
+    fn remove_file(&self, path: &Path) {
+        trace!("Removing '{}'", path.display());
+        self.apply(Change::RemoveFile(path.to_path_buf()));
+    }
+
+    /// Where outputs for `path` go in each variant's `dist` folder, without
+    /// an extension.
+    fn get_output_paths_for<'a>(&'a self, path: &Path) -> Vec<(&'a OutputVariant, PathBuf)> {
+        let Ok(rel) = path.strip_prefix(&self.options.src) else {
+            let error = OxcDiagnostic::error(format!(
+                "'{}' is not inside the src directory '{}'",
+                path.display(),
+                self.options.src.display()
+            ));
+            self.sender
+                .send(Message::Diagnostics((
+                    path.to_path_buf(),
+                    vec![Error::new(error)],
+                )))
+                .unwrap();
+            return vec![];
+        };
+        self.options
+            .variants
+            .iter()
+            .map(|variant| (variant, variant.dist.join(rel)))
+            .collect()
+    }
+
+    fn create_output_dirs_for(&self, dir: &Path) {
+        for (_, output_dir) in self.get_output_paths_for(dir) {
+            self.apply(Change::CreateDir(output_dir));
+        }
+    }
+
+    /// Extensions of code and declarations compiled from `path` in `variant`,
+    /// e.g. `js` and `d.ts`.
+    fn extensions(&self, variant: &OutputVariant, path: &Path) -> (&'static str, &'static str) {
+        self.compile_options.extensions(variant.module_format, path)
+    }
+
+    fn visit_file(&self, path: &Path) {
+        // declaration files have no code to compile
+        if Self::is_declaration_file(path) {
+            if self.options.isolated_declarations.is_some() {
+                self.copy_declaration_file(path);
+            }
+            return;
+        }
+        if self.is_asset(path) {
+            self.copy_to_dist(path);
+            return;
+        }
+
+        self.report_compiled(path);
+        let Some(source_text) = self.read_source(path) else {
+            return;
+        };
+        let cache_entry = self
+            .cache
+            .as_ref()
+            .map(|cache| cache.entry(path, &source_text));
+        let cached = cache_entry.as_ref().and_then(|entry| {
+            entry.load(|resolutions| resolutions_match(&self.compile_options, path, resolutions))
+        });
+        let files = match cached {
+            Some(files) => {
+                trace!("Using cached outputs for '{}'", path.display());
+                files
+            }
+            None => {
+                let Some(output) = self.compile(path, source_text) else {
+                    return;
+                };
+                // warnings should be shown on every build, so only files
+                // without any are cached
+                let cacheable = output.diagnostics.is_empty();
+                let resolutions = output.resolutions.clone();
+                let files = self.output_files(path, output);
+                if let Some(cache_entry) = cache_entry.filter(|_| cacheable) {
+                    cache_entry.store(&files, &resolutions);
+                }
+                files
+            }
+        };
+
+        for (output_path, contents) in files {
+            self.write(&output_path, contents);
+        }
+    }
+
+    /// Paths and contents of the files written for `path`, which compiled to
+    /// `output`.
+    fn output_files(&self, path: &Path, output: CompiledOutput) -> Vec<(PathBuf, String)> {
+        // todo: resolve relative paths. Idk if this is absolute or not
+        let CompiledOutput { code, .. } = output;
+
+        let mut files = vec![];
+        for ((variant, output_path), code) in self.get_output_paths_for(path).into_iter().zip(code)
+        {
+            let CompiledCode {
+                mut source_text,
+                source_map,
+                declarations,
+                declarations_map,
+            } = code;
+            let (js_ext, dts_ext) = self.extensions(variant, path);
+            let js_path = output_path.with_extension(js_ext);
+
+            // foo.js.map
+            if let (Some(source_map), Some(options)) = (source_map, &self.options.source_maps) {
+                let FinishedSourceMap { json, comment } = finish_source_map(
+                    options,
+                    &source_map,
+                    path,
+                    &js_path,
+                    &self.options.src,
+                    &variant.dist,
+                );
+                if let Some(json) = json {
+                    let map_path = output_path.with_extension(format!("{js_ext}.map"));
+                    files.push((map_path, json));
+                }
+                append_line(&mut source_text, &comment);
+            }
+
+            // foo.js
+            files.push((js_path, source_text));
+
+            let Some(mut declarations) = declarations else {
+                continue;
+            };
+            let dts_path = output_path.with_extension(dts_ext);
+
+            // foo.d.ts.map
+            if let Some(declarations_map) = declarations_map.as_ref() {
+                // declaration maps are only for editors, so they are never
+                // inlined and never include sources
+                let FinishedSourceMap { json, comment } = finish_source_map(
+                    &SourceMapOptions::default(),
+                    declarations_map,
+                    path,
+                    &dts_path,
+                    &self.options.src,
+                    &variant.dist,
+                );
+                let map_path = output_path.with_extension(format!("{dts_ext}.map"));
+                files.push((map_path, json.unwrap_or_default()));
+                append_line(&mut declarations, &comment);
+            }
+
+            // foo.d.ts
+            files.push((dts_path, declarations));
+        }
+        files
+    }
+
+    /// Copy a file into each `dist` folder as-is. Permissions are copied too.
+    fn copy_to_dist(&self, path: &Path) {
+        for (_, output_path) in self.get_output_paths_for(path) {
+            trace!("Copying '{}'", path.display());
+            self.apply(Change::Copy {
+                from: path.to_path_buf(),
+                to: output_path,
+            });
+        }
+    }
+
+    /// Copy a hand-written declaration file into `dist`, with its specifiers
+    /// rewritten like the ones in generated declarations.
+    fn copy_declaration_file(&self, path: &Path) {
+        let Some(source_text) = self.read_source(path) else {
+            return;
+        };
+        for (variant, output_path) in self.get_output_paths_for(path) {
+            match rewrite_declaration_file(
+                &self.compile_options,
+                variant.module_format,
+                path,
+                &source_text,
+            ) {
+                Some(rewritten) => self.write(&output_path, rewritten),
+                None => {
+                    trace!("Copying '{}'", path.display());
+                    self.apply(Change::Copy {
+                        from: path.to_path_buf(),
+                        to: output_path,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Every file that may be emitted for the source file `path` in
+    /// `variant`. `output_path` is from [`Self::get_output_paths_for`].
+    fn output_files_for(
+        &self,
+        variant: &OutputVariant,
+        path: &Path,
+        output_path: &Path,
+    ) -> Vec<PathBuf> {
+        if Self::is_declaration_file(path) || self.is_asset(path) {
+            return vec![output_path.to_path_buf()];
+        }
+        if !Self::is_allowed_ext(path) {
+            return vec![];
+        }
+        let (js_ext, dts_ext) = self.extensions(variant, path);
+        [
+            js_ext.to_string(),
+            format!("{js_ext}.map"),
+            dts_ext.to_string(),
+            format!("{dts_ext}.map"),
+        ]
+        .into_iter()
+        .map(|ext| output_path.with_extension(ext))
+        .collect()
+    }
+
+    /// Remove everything emitted for a source file (or directory) that has
+    /// been deleted.
+    fn remove_outputs_for(&self, path: &Path) {
+        for (variant, output_path) in self.get_output_paths_for(path) {
+            if output_path.is_dir() {
+                trace!("Removing '{}'", output_path.display());
+                self.apply(Change::RemoveDir(output_path));
+                continue;
+            }
+            for output_file in self.output_files_for(variant, path, &output_path) {
+                self.remove_file(&output_file);
+            }
+        }
+    }
+
+    /// Remove old outputs from each `dist` folder before building.
+    fn clean(&self, mode: CleanMode) {
+        for variant in &self.options.variants {
+            match mode {
+                CleanMode::All => {
+                    debug!("Cleaning '{}'", variant.dist.display());
+                    self.apply(Change::RemoveDir(variant.dist.clone()));
+                    self.apply(Change::CreateDir(variant.dist.clone()));
+                }
+                CleanMode::Stale => {
+                    debug!("Removing stale outputs from '{}'", variant.dist.display());
+                    self.remove_stale_outputs(variant, &variant.dist);
+                }
+            }
+        }
+    }
+
+    /// Remove files and folders in `dir`, which is inside `variant`'s `dist`
+    /// folder, that no source file would emit.
+    fn remove_stale_outputs(&self, variant: &OutputVariant, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.report_io_error(dir, "read directory", &e);
+                return;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.report_io_error(dir, "read directory", &e);
+                    continue;
+                }
+            };
+            let output_path = entry.path();
+            // other variants may be written inside this one, e.g. `dist/cjs`
+            if self.options.variants.iter().any(|v| v.dist == output_path) {
+                continue;
+            }
+            let rel = output_path.strip_prefix(&variant.dist).unwrap();
+            let source_path = self.options.src.join(rel);
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                if source_path.is_dir() && !self.is_excluded(&source_path) {
+                    self.remove_stale_outputs(variant, &output_path);
+                } else {
+                    trace!("Removing '{}'", output_path.display());
+                    self.apply(Change::RemoveDir(output_path));
+                }
+            } else if !self.has_source(variant, &output_path, &source_path) {
+                self.remove_file(&output_path);
+            }
+        }
+    }
+
+    /// Is `output_path` emitted by some source file? `source_path` is the
+    /// same path inside `src`, e.g. `src/foo.js.map` for `dist/foo.js.map`.
+    fn has_source(&self, variant: &OutputVariant, output_path: &Path, source_path: &Path) -> bool {
+        let Some(name) = source_path.file_name().and_then(|name| name.to_str()) else {
+            // can't be an output of ours, so leave it alone
+            return true;
+        };
+        // foo.d.ts.map may come from foo.ts, foo.d.ts, foo.d.ts.ts and so on
+        let stems = name.match_indices('.').map(|(i, _)| &name[..i]);
+        let candidates = stems
+            .flat_map(|stem| {
+                Self::ALLOWED_EXTS
+                    .iter()
+                    .map(move |ext| source_path.with_file_name(format!("{stem}.{ext}")))
+            })
+            .chain(std::iter::once(source_path.to_path_buf()));
+        candidates
+            .filter(|candidate| candidate.is_file() && !self.is_excluded(candidate))
+            .any(|candidate| {
+                let candidate_output = output_path.with_file_name(candidate.file_name().unwrap());
+                self.output_files_for(variant, &candidate, &candidate_output)
+                    .iter()
+                    .any(|output| output == output_path)
+            })
     }
 }
 
+/// The path a walk error is about, if any.
+fn walk_error_path(error: &WalkError) -> Option<&Path> {
+    match error {
+        WalkError::WithPath { path, .. } => Some(path),
+        WalkError::Loop { child, .. } => Some(child),
+        WalkError::WithDepth { err, .. } | WalkError::WithLineNumber { err, .. } => {
+            walk_error_path(err)
+        }
+        _ => None,
+    }
+}
+
+fn append_line(text: &mut String, line: &str) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(line);
+    text.push('\n');
+}
+
 impl ParallelVisitor for Walker {
     fn visit(&mut self, entry: Result<DirEntry, WalkError>) -> WalkState {
-        let Ok(ent) = entry else {
-            return WalkState::Continue;
+        let ent = match entry {
+            Ok(ent) => ent,
+            Err(e) => {
+                self.report_walk_error(&e);
+                return WalkState::Continue;
+            }
         };
 
         // create mirrored path in output directory
         if ent.path().is_dir() {
-            let output_dir = self.get_output_path_for(ent.path());
-            fs::create_dir_all(&output_dir).unwrap();
+            self.create_output_dirs_for(ent.path());
             return WalkState::Continue;
         }
 
-        // skip non-js/ts files
-        // TODO: copy over json, etc.
-        if !Self::is_allowed_ext(ent.path()) {
+        // skip non-js/ts files that aren't assets
+        if !Self::is_allowed_ext(ent.path()) && !self.is_asset(ent.path()) {
             return WalkState::Continue;
         }
 
-        // todo: resolve relative paths. Idk if this is absolute or not
-        let Some(CompiledOutput {
-            source_text,
-            source_map,
-            declarations,
-            declarations_map,
-        }) = self.compile(ent.path())
-        else {
-            return WalkState::Continue;
-        };
-        let output_path = self.get_output_path_for(ent.path());
+        self.visit_file(ent.path());
+
+        WalkState::Continue
+    }
+}
 
-        // foo.js
-        let js_path = output_path.with_extension("js");
-        fs::write(js_path, source_text).unwrap();
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        path::Path,
+        sync::mpsc::{self, Receiver},
+    };
 
-        // foo.js.map
-        if let Some(source_map) = source_map {
-            let map_path = output_path.with_extension("js.map");
-            fs::write(map_path, source_map.to_json_string()).unwrap();
-        }
+    use super::WalkerBuilder;
+    use crate::{
+        cli::{CliOptions, Root},
+        options::{MinifyOptions, OxbuildOptions},
+        reporter::{Message, ReportFormat},
+    };
 
-        // foo.d.ts
-        if let Some(declarations) = declarations {
-            let dts_path = output_path.with_extension("d.ts");
-            fs::write(dts_path, declarations).unwrap();
+    /// Write `files`, as `(path, contents)` relative to `root`.
+    fn write_files(root: &Path, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
         }
+    }
 
-        // foo.d.ts.map
-        if let Some(declarations_map) = declarations_map {
-            let map_path = output_path.with_extension("d.ts.map");
-            fs::write(map_path, declarations_map.to_json_string()).unwrap();
+    /// Options for the project in `root`, as if `oxbuild` was run there
+    /// without any flags.
+    fn options(root: &Path) -> OxbuildOptions {
+        let root = Root::new_explicit(root.canonicalize().unwrap()).unwrap();
+        let tsconfig = root.resolve_file(None, ["tsconfig.json"]).unwrap();
+        OxbuildOptions::new(CliOptions {
+            root,
+            config: None,
+            tsconfig,
+            num_threads: None,
+            watch: false,
+            clean: None,
+            no_cache: true,
+            mode: None,
+            defines: vec![],
+            minify: None,
+            format: ReportFormat::default(),
+            reports: vec![],
+        })
+        .unwrap()
+    }
+
+    /// A walker for `options` and the diagnostics it reports, which must be
+    /// kept alive while it runs.
+    fn walker(options: OxbuildOptions) -> (WalkerBuilder, Receiver<Message>) {
+        let (sender, diagnostics) = mpsc::channel();
+        (WalkerBuilder::new(options, sender), diagnostics)
+    }
+
+    /// A folder moved into `src` while watching only produces an event for
+    /// the folder itself.
+    #[test]
+    fn test_rebuild_new_directory() {
+        let root = tempfile::tempdir().unwrap();
+        write_files(
+            root.path(),
+            &[
+                ("package.json", "{}"),
+                ("tsconfig.json", r#"{ "exclude": ["src/sub/skip.ts"] }"#),
+                ("src/index.ts", "export const a = 1;"),
+            ],
+        );
+        let options = options(root.path());
+        let (src, dist) = (options.src.clone(), options.variants[0].dist.clone());
+        let (mut walker, _diagnostics) = walker(options);
+        walker.walk(1);
+        assert!(dist.join("index.js").is_file());
+
+        write_files(
+            &src,
+            &[
+                ("sub/m.js", "export const m = 1;"),
+                ("sub/deep/n.ts", "export const n: number = 1;"),
+                ("sub/skip.ts", "export const skip = 1;"),
+            ],
+        );
+        walker.rebuild([src.join("sub")]);
+        assert!(dist.join("sub/m.js").is_file());
+        assert!(dist.join("sub/deep/n.js").is_file());
+        assert!(!dist.join("sub/skip.js").exists());
+    }
+
+    /// Files emitted for `index.ts`, `esm.mts`, `cjs.cts` and `view.tsx`,
+    /// with declarations and declaration maps, as `(package type, module,
+    /// [ts, mts, cts, tsx] output names)`.
+    const OUTPUT_FILES: [(&str, &str, [[&str; 4]; 4]); 4] = [
+        (
+            "module",
+            "esnext",
+            [
+                ["index.js", "index.js.map", "index.d.ts", "index.d.ts.map"],
+                ["esm.mjs", "esm.mjs.map", "esm.d.mts", "esm.d.mts.map"],
+                ["cjs.cjs", "cjs.cjs.map", "cjs.d.cts", "cjs.d.cts.map"],
+                ["view.js", "view.js.map", "view.d.ts", "view.d.ts.map"],
+            ],
+        ),
+        (
+            "commonjs",
+            "esnext",
+            [
+                [
+                    "index.mjs",
+                    "index.mjs.map",
+                    "index.d.mts",
+                    "index.d.mts.map",
+                ],
+                ["esm.mjs", "esm.mjs.map", "esm.d.mts", "esm.d.mts.map"],
+                ["cjs.cjs", "cjs.cjs.map", "cjs.d.cts", "cjs.d.cts.map"],
+                ["view.mjs", "view.mjs.map", "view.d.mts", "view.d.mts.map"],
+            ],
+        ),
+        (
+            "module",
+            "commonjs",
+            [
+                [
+                    "index.cjs",
+                    "index.cjs.map",
+                    "index.d.cts",
+                    "index.d.cts.map",
+                ],
+                ["esm.mjs", "esm.mjs.map", "esm.d.mts", "esm.d.mts.map"],
+                ["cjs.cjs", "cjs.cjs.map", "cjs.d.cts", "cjs.d.cts.map"],
+                ["view.cjs", "view.cjs.map", "view.d.cts", "view.d.cts.map"],
+            ],
+        ),
+        (
+            "commonjs",
+            "commonjs",
+            [
+                ["index.js", "index.js.map", "index.d.ts", "index.d.ts.map"],
+                ["esm.mjs", "esm.mjs.map", "esm.d.mts", "esm.d.mts.map"],
+                ["cjs.cjs", "cjs.cjs.map", "cjs.d.cts", "cjs.d.cts.map"],
+                ["view.js", "view.js.map", "view.d.ts", "view.d.ts.map"],
+            ],
+        ),
+    ];
+
+    #[test]
+    fn test_output_files() {
+        for (package_type, module, expected) in OUTPUT_FILES {
+            let root = tempfile::tempdir().unwrap();
+            let tsconfig = format!(
+                r#"{{ "compilerOptions": {{ "module": "{module}", "isolatedDeclarations": true, "declarationMap": true }} }}"#
+            );
+            write_files(
+                root.path(),
+                &[
+                    (
+                        "package.json",
+                        &format!(r#"{{ "type": "{package_type}" }}"#),
+                    ),
+                    ("tsconfig.json", &tsconfig),
+                    ("src/index.ts", "export const a: number = 1;"),
+                    ("src/esm.mts", "export const b: number = 1;"),
+                    ("src/cjs.cts", "export const c: number = 1;"),
+                    ("src/view.tsx", "export const v: unknown = <div />;"),
+                ],
+            );
+            let options = options(root.path());
+            let (src, dist) = (options.src.clone(), options.variants[0].dist.clone());
+            let (mut builder, _diagnostics) = walker(options);
+            let walker = builder.walker();
+            let context = format!("{module} output in a {package_type} package");
+
+            for (source, expected) in ["index.ts", "esm.mts", "cjs.cts", "view.tsx"]
+                .into_iter()
+                .zip(expected)
+            {
+                let path = src.join(source);
+                let expected: Vec<_> = expected.iter().map(|name| dist.join(name)).collect();
+                let mut files = vec![];
+                for (variant, output_path) in walker.get_output_paths_for(&path) {
+                    files.extend(walker.output_files_for(variant, &path, &output_path));
+                }
+                assert_eq!(files, expected, "{source}, {context}");
+            }
+
+            // what's written matches what would be removed for each source
+            builder.walk(1);
+            let mut written: Vec<_> = fs::read_dir(&dist)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            written.sort();
+            let mut expected: Vec<_> = expected.concat();
+            expected.sort_unstable();
+            assert_eq!(written, expected, "{context}");
         }
+    }
 
-        WalkState::Continue
+    /// `files` wins over `exclude`, which wins over `include`, even for files
+    /// in excluded folders.
+    #[test]
+    fn test_file_overrides() {
+        let root = tempfile::tempdir().unwrap();
+        write_files(
+            root.path(),
+            &[
+                ("package.json", "{}"),
+                (
+                    "tsconfig.json",
+                    r#"{ "include": ["src"], "exclude": ["src/gen", "src/skip.ts"], "files": ["src/gen/keep.ts"] }"#,
+                ),
+                ("src/index.ts", "export const a = 1;"),
+                ("src/skip.ts", "export const a = 1;"),
+                ("src/gen/keep.ts", "export const a = 1;"),
+                ("src/gen/other.ts", "export const a = 1;"),
+            ],
+        );
+        let options = options(root.path());
+        let (src, dist) = (options.src.clone(), options.variants[0].dist.clone());
+        let (mut builder, _diagnostics) = walker(options);
+        builder.walk(1);
+        assert!(dist.join("index.js").is_file());
+        assert!(dist.join("gen/keep.js").is_file());
+        assert!(!dist.join("skip.js").exists());
+        assert!(!dist.join("gen/other.js").exists());
+
+        // changes to excluded files are ignored while watching too
+        builder.rebuild([src.join("gen/other.ts"), src.join("skip.ts")]);
+        assert!(!dist.join("skip.js").exists());
+        assert!(!dist.join("gen/other.js").exists());
+    }
+
+    #[test]
+    fn test_cache() {
+        let root = tempfile::tempdir().unwrap();
+        write_files(
+            root.path(),
+            &[
+                ("package.json", "{}"),
+                ("src/a.ts", "export const a = 1;"),
+                ("src/b.ts", "export const b = 1;"),
+            ],
+        );
+        let cache_dir = root.path().join("cache");
+        let cached_options = || {
+            let mut options = options(root.path());
+            options.cache_dir = Some(cache_dir.clone());
+            options
+        };
+        let entries = || fs::read_dir(&cache_dir).unwrap().count();
+        let options = cached_options();
+        let (src, dist) = (options.src.clone(), options.variants[0].dist.clone());
+
+        let (mut builder, _diagnostics) = walker(options);
+        builder.walk(1);
+        assert_eq!(entries(), 2);
+        let unminified = fs::read_to_string(dist.join("a.js")).unwrap();
+
+        // options outputs depend on aren't served stale outputs
+        let mut options = cached_options();
+        options.minify = MinifyOptions::ALL;
+        let (mut builder, _diagnostics) = walker(options);
+        builder.walk(1);
+        assert_ne!(fs::read_to_string(dist.join("a.js")).unwrap(), unminified);
+
+        // deleted while watching
+        fs::remove_file(src.join("b.ts")).unwrap();
+        builder.rebuild([src.join("b.ts")]);
+        assert_eq!(entries(), 1);
+
+        // deleted between builds
+        fs::remove_file(src.join("a.ts")).unwrap();
+        let (mut builder, _diagnostics) = walker(cached_options());
+        builder.walk(1);
+        assert_eq!(entries(), 0);
     }
 }
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use oxc::transformer::JsxRuntime;
+
+use crate::{compiler::Resolutions, options::OxbuildOptions};
+
+/// Compiled outputs saved between builds, so unchanged files don't need to be
+/// compiled again.
+///
+/// Each source file has one entry, keyed by a hash of its contents and of
+/// every option that affects its outputs. Entries also record the source
+/// files relative imports named, since those decide the imports' extensions.
+#[derive(Debug)]
+pub(super) struct Cache {
+    dir: PathBuf,
+    /// Hash of oxbuild's version and the options outputs depend on.
+    fingerprint: String,
+    /// Entries for source files seen since the last full walk. Everything
+    /// else in `dir` belongs to files that are gone.
+    used: Mutex<HashSet<PathBuf>>,
+}
+
+/// Where a source file's outputs are cached.
+pub(super) struct CacheEntry {
+    path: PathBuf,
+    key: String,
+}
+
+/// Contents of a cache entry. `F` is a list of output paths and their
+/// contents, and `R` the [`Resolutions`] they were compiled with.
+#[derive(Serialize, Deserialize)]
+struct CacheEntryFile<F, R> {
+    key: String,
+    files: F,
+    resolutions: R,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, options: &OxbuildOptions) -> Self {
+        let fingerprint = hash(fingerprint(options).iter().map(String::as_str));
+        debug!("Using cache at '{}' ({fingerprint})", dir.display());
+        Self {
+            dir,
+            fingerprint,
+            used: Mutex::default(),
+        }
+    }
+
+    pub fn entry(&self, source_path: &Path, source_text: &str) -> CacheEntry {
+        let path = self.entry_path(source_path);
+        self.used.lock().unwrap().insert(path.clone());
+        CacheEntry {
+            path,
+            key: hash([&self.fingerprint, source_text]),
+        }
+    }
+
+    /// Forget entries seen so far, before walking every source file again.
+    pub fn reset(&self) {
+        self.used.lock().unwrap().clear();
+    }
+
+    /// Delete the entry for `source_path`, which no longer exists.
+    pub fn remove(&self, source_path: &Path) {
+        let path = self.entry_path(source_path);
+        self.used.lock().unwrap().remove(&path);
+        remove_entry(&path);
+    }
+
+    /// Delete entries for source files that weren't seen since the last
+    /// [`reset`](Self::reset), e.g. because they were deleted between builds.
+    pub fn prune(&self) {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("Failed to read cache '{}': {e}", self.dir.display());
+                return;
+            }
+        };
+        let used = self.used.lock().unwrap();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !used.contains(&path) {
+                trace!("Pruning cache entry '{}'", path.display());
+                remove_entry(&path);
+            }
+        }
+    }
+
+    fn entry_path(&self, source_path: &Path) -> PathBuf {
+        let name = hash([&*source_path.to_string_lossy()]);
+        self.dir.join(name).with_extension("json")
+    }
+}
+
+impl CacheEntry {
+    /// Outputs saved by a previous build, if the source file and options
+    /// haven't changed since, and `is_current` accepts the saved resolutions.
+    pub fn load(
+        &self,
+        is_current: impl FnOnce(&Resolutions) -> bool,
+    ) -> Option<Vec<(PathBuf, String)>> {
+        let json = fs::read_to_string(&self.path).ok()?;
+        let entry: CacheEntryFile<Vec<(PathBuf, String)>, Resolutions> =
+            serde_json::from_str(&json).ok()?;
+        (entry.key == self.key && is_current(&entry.resolutions)).then_some(entry.files)
+    }
+
+    /// Save outputs for the next build. Failures are logged, since a missing
+    /// entry only makes the next build slower.
+    pub fn store(self, files: &[(PathBuf, String)], resolutions: &Resolutions) {
+        let json = serde_json::to_string(&CacheEntryFile {
+            key: self.key,
+            files,
+            resolutions,
+        })
+        .unwrap();
+        let result = fs::create_dir_all(self.path.parent().unwrap())
+            .and_then(|()| fs::write(&self.path, json));
+        if let Err(e) = result {
+            warn!("Failed to write cache entry '{}': {e}", self.path.display());
+        }
+    }
+}
+
+fn remove_entry(path: &Path) {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            warn!("Failed to remove cache entry '{}': {e}", path.display());
+        }
+        _ => {}
+    }
+}
+
+/// oxbuild's version and every option compiled outputs depend on, one
+/// `name=value` part each.
+///
+/// `options` is destructured without `..` so that new options have to be
+/// listed here, or explicitly ignored.
+fn fingerprint(options: &OxbuildOptions) -> Vec<String> {
+    let OxbuildOptions {
+        root: _,
+        isolated_declarations,
+        src,
+        variants,
+        overrides: _,
+        assets: _,
+        source_maps,
+        jsx,
+        defines,
+        define_config: _,
+        minify,
+        strip_comments,
+        paths,
+        preserve_jsx,
+        module_package,
+        no_emit_on_error: _,
+        num_threads: _,
+        watch: _,
+        clean: _,
+        cache_dir: _,
+        config_diagnostics: _,
+    } = options;
+
+    let mut parts = vec![
+        format!("version={}", env!("CARGO_PKG_VERSION")),
+        format!("src={}", src.display()),
+        format!("modulePackage={module_package}"),
+        format!("preserveJsx={preserve_jsx}"),
+        format!("stripComments={strip_comments}"),
+        format!("minify.whitespace={}", minify.whitespace),
+        format!("minify.compress={}", minify.compress),
+        format!("minify.mangle={}", minify.mangle),
+    ];
+    for variant in variants {
+        parts.push(format!("variant.dist={}", variant.dist.display()));
+        parts.push(format!("variant.target={}", variant.target));
+        parts.push(format!("variant.module={}", variant.module_format));
+    }
+    if let Some(declarations) = isolated_declarations {
+        parts.push(format!(
+            "d.ts.stripInternal={}",
+            declarations.strip_internal
+        ));
+        parts.push(format!("d.ts.map={}", declarations.declaration_map));
+    }
+    if let Some(source_maps) = source_maps {
+        parts.push(format!("sourceMap.inline={}", source_maps.inline));
+        parts.push(format!(
+            "sourceMap.inlineSources={}",
+            source_maps.inline_sources
+        ));
+        parts.push(format!(
+            "sourceMap.sourceRoot={:?}",
+            source_maps.source_root
+        ));
+        parts.push(format!("sourceMap.mapRoot={:?}", source_maps.map_root));
+    }
+    let runtime = match jsx.runtime {
+        JsxRuntime::Classic => "classic",
+        JsxRuntime::Automatic => "automatic",
+    };
+    parts.extend([
+        format!("jsx.runtime={runtime}"),
+        format!("jsx.development={}", jsx.development),
+        format!("jsx.importSource={:?}", jsx.import_source),
+        format!("jsx.pragma={:?}", jsx.pragma),
+        format!("jsx.pragmaFrag={:?}", jsx.pragma_frag),
+        format!("jsx.plugin={}", jsx.jsx_plugin),
+        format!("jsx.displayNamePlugin={}", jsx.display_name_plugin),
+    ]);
+    for (name, value) in defines {
+        parts.push(format!("define.{name}={value}"));
+    }
+    if let Some(paths) = paths {
+        parts.extend(paths.fingerprint());
+    }
+    parts
+}
+
+/// Hex-encoded SHA-1 of `parts`, which are separated so that moving text from
+/// one part to the next changes the hash.
+fn hash<'a>(parts: impl IntoIterator<Item = &'a str>) -> String {
+    let mut hasher = Sha1::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .fold(String::with_capacity(40), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
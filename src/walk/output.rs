@@ -0,0 +1,417 @@
+use std::{
+    fs, io, mem,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// A change the walker makes to an output folder.
+#[derive(Debug)]
+pub(super) enum Change {
+    CreateDir(PathBuf),
+    Write(PathBuf, Vec<u8>),
+    /// Copy a file as-is, including its permissions.
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Remove a file. Files that are already gone are ignored.
+    RemoveFile(PathBuf),
+    /// Remove a directory and everything in it. Directories that are already
+    /// gone are ignored.
+    RemoveDir(PathBuf),
+}
+
+impl Change {
+    /// The output path this change affects.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::CreateDir(path)
+            | Self::Write(path, _)
+            | Self::RemoveFile(path)
+            | Self::RemoveDir(path) => path,
+            Self::Copy { to, .. } => to,
+        }
+    }
+
+    /// Describes this change for error messages, e.g. "Failed to {action}".
+    pub fn action(&self) -> &'static str {
+        match self {
+            Self::CreateDir(_) => "create directory",
+            Self::Write(..) => "write",
+            Self::Copy { .. } => "copy to",
+            Self::RemoveFile(_) | Self::RemoveDir(_) => "remove",
+        }
+    }
+
+    /// This change, made inside the folder `to` instead of `from`.
+    fn rebase(self, from: &Path, to: &Path) -> Self {
+        let rebase = |path: PathBuf| match path.strip_prefix(from) {
+            Ok(rel) if rel.as_os_str().is_empty() => to.to_path_buf(),
+            Ok(rel) => to.join(rel),
+            Err(_) => path,
+        };
+        match self {
+            Self::CreateDir(path) => Self::CreateDir(rebase(path)),
+            Self::Write(path, contents) => Self::Write(rebase(path), contents),
+            Self::Copy { from, to } => Self::Copy {
+                from,
+                to: rebase(to),
+            },
+            Self::RemoveFile(path) => Self::RemoveFile(rebase(path)),
+            Self::RemoveDir(path) => Self::RemoveDir(rebase(path)),
+        }
+    }
+
+    fn apply(&self) -> io::Result<()> {
+        match self {
+            Self::CreateDir(path) => fs::create_dir_all(path),
+            Self::Write(path, contents) => fs::write(path, contents),
+            Self::Copy { from, to } => fs::copy(from, to).map(|_| ()),
+            Self::RemoveFile(path) => ignore_not_found(fs::remove_file(path)),
+            Self::RemoveDir(path) => ignore_not_found(fs::remove_dir_all(path)),
+        }
+    }
+}
+
+/// Applies changes to output folders, either immediately or, for
+/// `noEmitOnError`, all at once after the build has succeeded.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Output {
+    /// Changes waiting for the build to finish. [`None`] when changes are
+    /// applied immediately.
+    staged: Option<Arc<Mutex<Vec<Change>>>>,
+}
+
+impl Output {
+    pub fn new(stage: bool) -> Self {
+        Self {
+            staged: stage.then(Default::default),
+        }
+    }
+
+    /// Apply `change`, or stage it for later.
+    pub fn apply(&self, change: Change) -> Result<(), (Change, io::Error)> {
+        match &self.staged {
+            Some(staged) => {
+                staged.lock().unwrap().push(change);
+                Ok(())
+            }
+            None => Self::apply_now(change),
+        }
+    }
+
+    pub fn apply_now(change: Change) -> Result<(), (Change, io::Error)> {
+        change.apply().map_err(|e| (change, e))
+    }
+
+    /// Take every staged change, in the order they were made.
+    pub fn take_staged(&self) -> Vec<Change> {
+        self.staged
+            .as_ref()
+            .map(|staged| mem::take(&mut *staged.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
+    /// Apply staged `changes` to the output folders in `dists` they were made
+    /// in. Each folder is copied into a hidden sibling folder, changed there,
+    /// and then renamed into place, so a failure leaves `dist` as it was.
+    ///
+    /// Folders are swapped one at a time, innermost first, so nested output
+    /// folders like `dist/cjs` are moved into their parent's new folder.
+    pub fn commit(changes: Vec<Change>, dists: &[PathBuf]) -> Result<(), CommitError> {
+        let mut by_dist: Vec<(&Path, Vec<Change>)> = vec![];
+        for change in changes {
+            let dist = dists
+                .iter()
+                .filter(|dist| change.path().starts_with(dist))
+                .max_by_key(|dist| dist.components().count());
+            let Some(dist) = dist else {
+                // not inside an output folder, so there's nothing to swap
+                Self::apply_now(change).map_err(CommitError::from_change)?;
+                continue;
+            };
+            match by_dist.iter_mut().find(|(d, _)| d == dist) {
+                Some((_, changes)) => changes.push(change),
+                None => by_dist.push((dist, vec![change])),
+            }
+        }
+        by_dist.sort_by_key(|(dist, _)| std::cmp::Reverse(dist.components().count()));
+
+        let mut staged = vec![];
+        for (dist, changes) in by_dist {
+            let staging = hidden_sibling(dist, STAGED);
+            staged.push((dist, staging.clone()));
+            if let Err(e) = stage(dist, &staging, dists, changes) {
+                for (_, staging) in &staged {
+                    let _ = fs::remove_dir_all(staging);
+                }
+                return Err(e);
+            }
+        }
+
+        let mut journal = Journal::default();
+        for (dist, staging) in &staged {
+            if let Err(e) = swap(dist, staging, dists, &mut journal) {
+                // nested output folders may have been moved into a staging
+                // folder, so those are only removed once everything is back
+                if journal.undo() {
+                    for (_, staging) in &staged {
+                        let _ = fs::remove_dir_all(staging);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        for (dist, _) in staged.iter().rev() {
+            let old = hidden_sibling(dist, OLD);
+            ignore_not_found(fs::remove_dir_all(&old))
+                .map_err(|e| CommitError::new(&old, "remove", e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Renames made while swapping output folders, so they can be undone if a
+/// later swap fails.
+#[derive(Debug, Default)]
+struct Journal {
+    renames: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Journal {
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)?;
+        self.renames.push((from.to_path_buf(), to.to_path_buf()));
+        Ok(())
+    }
+
+    /// Undo every rename, newest first. Returns `false` if any of them
+    /// couldn't be undone.
+    fn undo(&mut self) -> bool {
+        let mut undone = true;
+        for (from, to) in self.renames.drain(..).rev() {
+            if let Err(e) = fs::rename(&to, &from) {
+                warn!(
+                    "Failed to move '{}' back to '{}': {e}",
+                    to.display(),
+                    from.display()
+                );
+                undone = false;
+            }
+        }
+        undone
+    }
+}
+
+/// A step of [`Output::commit`] that failed.
+#[derive(Debug)]
+pub(super) struct CommitError {
+    pub path: PathBuf,
+    /// Describes the step, e.g. "Failed to {action}".
+    pub action: &'static str,
+    pub error: io::Error,
+}
+
+impl CommitError {
+    fn new(path: &Path, action: &'static str, error: io::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            action,
+            error,
+        }
+    }
+
+    fn from_change((change, error): (Change, io::Error)) -> Self {
+        Self::new(change.path(), change.action(), error)
+    }
+}
+
+/// Suffixes of the folders [`Output::commit`] creates next to `dist`.
+const STAGED: &str = "oxbuild-staged";
+const OLD: &str = "oxbuild-old";
+
+/// A hidden folder next to `dist`, e.g. `.dist.oxbuild-staged`.
+fn hidden_sibling(dist: &Path, suffix: &str) -> PathBuf {
+    let name = dist.file_name().unwrap_or_default().to_string_lossy();
+    dist.with_file_name(format!(".{name}.{suffix}"))
+}
+
+/// Copy `dist` into `staging`, except for other output folders nested in it,
+/// and apply `changes` there.
+fn stage(
+    dist: &Path,
+    staging: &Path,
+    dists: &[PathBuf],
+    changes: Vec<Change>,
+) -> Result<(), CommitError> {
+    ignore_not_found(fs::remove_dir_all(staging))
+        .map_err(|e| CommitError::new(staging, "remove", e))?;
+    if dist.is_dir() {
+        copy_dir(dist, staging, dists)?;
+    } else {
+        fs::create_dir_all(staging)
+            .map_err(|e| CommitError::new(staging, "create directory", e))?;
+    }
+    for change in changes {
+        // report errors for the path in `dist`, which is what users know
+        Output::apply_now(change.rebase(dist, staging))
+            .map_err(|(change, e)| CommitError::from_change((change.rebase(staging, dist), e)))?;
+    }
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path, dists: &[PathBuf]) -> Result<(), CommitError> {
+    fs::create_dir_all(to).map_err(|e| CommitError::new(to, "create directory", e))?;
+    let entries = fs::read_dir(from).map_err(|e| CommitError::new(from, "read directory", e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| CommitError::new(from, "read directory", e))?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // nested output folders are swapped separately
+        if dists.contains(&path)
+            || (name.starts_with('.') && (name.ends_with(STAGED) || name.ends_with(OLD)))
+        {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            copy_dir(&path, &target, dists)?;
+        } else {
+            fs::copy(&path, &target).map_err(|e| CommitError::new(&target, "copy to", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace `dist` with `staging`, moving output folders nested in `dist`
+/// along. The old `dist` is kept in a hidden sibling folder until every
+/// folder has been swapped.
+fn swap(
+    dist: &Path,
+    staging: &Path,
+    dists: &[PathBuf],
+    journal: &mut Journal,
+) -> Result<(), CommitError> {
+    for nested in dists {
+        let is_child = nested != dist
+            && nested.starts_with(dist)
+            && !dists.iter().any(|between| {
+                between != dist
+                    && between != nested
+                    && between.starts_with(dist)
+                    && nested.starts_with(between)
+            });
+        if !is_child || !nested.is_dir() {
+            continue;
+        }
+        let target = staging.join(nested.strip_prefix(dist).unwrap());
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| CommitError::new(parent, "create directory", e))?;
+        }
+        ignore_not_found(fs::remove_dir_all(&target))
+            .map_err(|e| CommitError::new(&target, "remove", e))?;
+        journal
+            .rename(nested, &target)
+            .map_err(|e| CommitError::new(nested, "move", e))?;
+    }
+
+    let old = hidden_sibling(dist, OLD);
+    ignore_not_found(fs::remove_dir_all(&old)).map_err(|e| CommitError::new(&old, "remove", e))?;
+    if dist.exists() {
+        journal
+            .rename(dist, &old)
+            .map_err(|e| CommitError::new(dist, "move", e))?;
+    } else if let Some(parent) = dist.parent() {
+        fs::create_dir_all(parent).map_err(|e| CommitError::new(parent, "create directory", e))?;
+    }
+    journal
+        .rename(staging, dist)
+        .map_err(|e| CommitError::new(dist, "replace", e))
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    /// Paths relative to `root` and contents of every file in it.
+    fn files(root: &Path) -> Vec<(String, String)> {
+        let mut files = vec![];
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let name = path.strip_prefix(root).unwrap().to_string_lossy();
+                files.push((name.into_owned(), fs::read_to_string(&path).unwrap()));
+            }
+        }
+        files.sort();
+        files
+    }
+
+    /// `dist` with an output folder for CommonJS nested in it.
+    fn nested_dists(root: &Path) -> Vec<PathBuf> {
+        let (dist, cjs) = (root.join("dist"), root.join("dist/cjs"));
+        fs::create_dir_all(&cjs).unwrap();
+        fs::write(dist.join("index.js"), "old esm").unwrap();
+        fs::write(cjs.join("index.js"), "old cjs").unwrap();
+        vec![dist, cjs]
+    }
+
+    fn changes(dists: &[PathBuf]) -> Vec<Change> {
+        vec![
+            Change::Write(dists[0].join("index.js"), b"new esm".to_vec()),
+            Change::Write(dists[1].join("index.js"), b"new cjs".to_vec()),
+        ]
+    }
+
+    #[test]
+    fn test_commit() {
+        let root = tempfile::tempdir().unwrap();
+        let dists = nested_dists(root.path());
+        Output::commit(changes(&dists), &dists).unwrap();
+        assert_eq!(
+            files(root.path()),
+            [
+                ("dist/cjs/index.js".into(), "new cjs".into()),
+                ("dist/index.js".into(), "new esm".into()),
+            ]
+        );
+    }
+
+    /// `dist/cjs` is swapped and moved into the staging folder for `dist`
+    /// before `dist` fails to swap. Both are put back as they were.
+    #[test]
+    fn test_commit_failure() {
+        let root = tempfile::tempdir().unwrap();
+        let dists = nested_dists(root.path());
+        // a file where the old `dist` would be moved to can't be removed as a
+        // folder
+        fs::write(hidden_sibling(&dists[0], OLD), "").unwrap();
+
+        let error = Output::commit(changes(&dists), &dists).unwrap_err();
+        assert_eq!(error.path, hidden_sibling(&dists[0], OLD));
+        fs::remove_file(hidden_sibling(&dists[0], OLD)).unwrap();
+        assert_eq!(
+            files(root.path()),
+            [
+                ("dist/cjs/index.js".into(), "old cjs".into()),
+                ("dist/index.js".into(), "old esm".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+
+use oxc::sourcemap::SourceMap;
+
+use crate::{options::SourceMapOptions, path::relative_url};
+
+/// A source map, ready to be written alongside the file it maps.
+pub(super) struct FinishedSourceMap {
+    /// Contents of the `.map` file. [`None`] for inline source maps.
+    pub json: Option<String>,
+    /// `sourceMappingURL` comment to append to the mapped file.
+    pub comment: String,
+}
+
+/// Fill in the paths and sources of a source map for `output_path`, which was
+/// compiled from `source_path`.
+///
+/// `src` and `dist` are the source and output folders.
+pub(super) fn finish_source_map(
+    options: &SourceMapOptions,
+    map: &SourceMap,
+    source_path: &Path,
+    output_path: &Path,
+    src: &Path,
+    dist: &Path,
+) -> FinishedSourceMap {
+    let output_dir = output_path.parent().unwrap();
+    let file_name = output_path.file_name().unwrap().to_string_lossy();
+
+    let mut json = map.to_json();
+    json.file = Some(file_name.to_string());
+    // debuggers resolve sources relative to `sourceRoot` when it's set, and
+    // relative to the map otherwise.
+    let source = match &options.source_root {
+        Some(source_root) => {
+            json.source_root = Some(source_root.clone());
+            relative_url(src, source_path)
+        }
+        None => relative_url(output_dir, source_path),
+    };
+    json.sources = vec![source];
+    if !options.inline_sources {
+        json.sources_content = None;
+    }
+    let map = SourceMap::from_json(json).unwrap();
+
+    if options.inline {
+        return FinishedSourceMap {
+            json: None,
+            comment: format!("//# sourceMappingURL={}", map.to_data_url()),
+        };
+    }
+
+    let map_name = format!("{file_name}.map");
+    let url = match &options.map_root {
+        Some(map_root) => {
+            let dir = relative_url(dist, output_dir);
+            let map_root = map_root.trim_end_matches('/');
+            if dir.is_empty() {
+                format!("{map_root}/{map_name}")
+            } else {
+                format!("{map_root}/{dir}/{map_name}")
+            }
+        }
+        None => map_name,
+    };
+    FinishedSourceMap {
+        json: Some(map.to_json_string()),
+        comment: format!("//# sourceMappingURL={url}"),
+    }
+}
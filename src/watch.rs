@@ -0,0 +1,86 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use miette::{IntoDiagnostic, Result, WrapErr};
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{
+        event::{AccessKind, AccessMode, ModifyKind},
+        EventKind, RecommendedWatcher, RecursiveMode,
+    },
+    DebounceEventResult, Debouncer, RecommendedCache,
+};
+
+/// How long to wait for a burst of file system events to settle before
+/// triggering a rebuild. Editors often write files in several steps.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Watches a source directory for changes.
+#[must_use]
+pub struct Watcher {
+    // dropping the debouncer stops the watcher thread
+    _debouncer: Debouncer<RecommendedWatcher, RecommendedCache>,
+    events: Receiver<DebounceEventResult>,
+}
+
+impl Watcher {
+    pub fn new(src: &Path) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, None, tx)
+            .into_diagnostic()
+            .context("Failed to start file watcher")?;
+        debouncer
+            .watch(src, RecursiveMode::Recursive)
+            .into_diagnostic()
+            .with_context(|| format!("Failed to watch '{}'", src.display()))?;
+        debug!("Watching '{}' for changes", src.display());
+
+        Ok(Self {
+            _debouncer: debouncer,
+            events,
+        })
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = BTreeSet<PathBuf>;
+
+    /// Block until the next batch of changed paths is available. Returns
+    /// [`None`] once the watcher has shut down.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.events.recv().ok()? {
+                Ok(events) => {
+                    let changed: BTreeSet<_> = events
+                        .into_iter()
+                        .filter(|e| is_change(e.kind))
+                        .flat_map(|e| e.event.paths)
+                        .collect();
+                    if !changed.is_empty() {
+                        return Some(changed);
+                    }
+                }
+                Err(errors) => {
+                    for e in errors {
+                        warn!("File watcher error: {e}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Does this event mean the contents of a file may have changed? We read source
+/// files while compiling them, so reacting to open/read events would cause an
+/// endless rebuild loop.
+fn is_change(kind: EventKind) -> bool {
+    match kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        _ => true,
+    }
+}